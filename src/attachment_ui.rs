@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+
+use druid::{Application, EventCtx, FileDialogOptions, FileInfo, HotKey, Selector, SysMods, Widget, WidgetExt};
+use druid::widget;
+use tracing::error;
+use crate::AppState;
+use crate::core_interface::{CoreRequest, CORE_SUPPORT, SEND_TO_CORE};
+use crate::data::attachment_data::{Attachment, FileAction, TransferState};
+use crate::helper::helper_functions;
+
+const ATTACHMENT_FILES_SELECTED: Selector<Vec<FileInfo>> = Selector::new("polysoft.druid-demo.attachment_files_selected");
+const SAVE_LOCATION_CHOSEN: Selector<FileInfo> = Selector::new("polysoft.druid-demo.attachment_save_location_chosen");
+const SAVE_CANCELLED: Selector<()> = Selector::new("polysoft.druid-demo.attachment_save_cancelled");
+// Sent by the file cards with the message's and the attachment's id. Files that aren't
+// downloaded yet are downloaded first.
+pub(crate) const SAVE_ATTACHMENT: Selector<(u32, u32)> = Selector::new("polysoft.druid-demo.save_attachment");
+pub(crate) const OPEN_ATTACHMENT: Selector<(u32, u32)> = Selector::new("polysoft.druid-demo.open_attachment");
+/// Sent by the x on an attachment in the tray, with the attachment's id
+const REMOVE_PENDING_ATTACHMENT: Selector<u32> = Selector::new("polysoft.druid-demo.remove_pending_attachment");
+
+/// What clipboard images are offered as on each platform (GTK, macOS, Windows)
+const CLIPBOARD_PNG_FORMATS: &[&str] = &["image/png", "public.png", "PNG"];
+const PASTED_IMAGE_NAME: &str = "pasted-image.png";
+const THUMBNAIL_SIZE: f64 = 40.0;
+const TRAY_ITEM_NAME_WIDTH: f64 = 120.0;
+
+/// Opens the file dialog. The chosen files are added to the composer.
+pub(crate) fn open_attachment_dialog(ctx: &mut EventCtx) {
+    ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(
+        FileDialogOptions::new()
+            .multi_selection()
+            .title("Attach Files")
+            .accept_multiple_command(ATTACHMENT_FILES_SELECTED)
+    ));
+}
+
+/// Handles the file dialogs, the tray's remove buttons and the file card actions.
+/// Returns whether the command was one of them.
+pub(crate) fn handle_attachment_command(ctx: &mut EventCtx, cmd: &druid::Command, data: &mut AppState) -> bool {
+    if let Some(files) = cmd.get(ATTACHMENT_FILES_SELECTED) {
+        let paths = files.iter().map(|file| file.path().to_path_buf()).collect();
+        load_files_in_background(ctx, paths);
+    } else if let Some(attachment_id) = cmd.get(REMOVE_PENDING_ATTACHMENT) {
+        data.remove_pending_attachment(*attachment_id);
+    } else if let Some((message_id, attachment_id)) = cmd.get(SAVE_ATTACHMENT) {
+        run_file_action(ctx, data, *message_id, *attachment_id, FileAction::Save);
+    } else if let Some((message_id, attachment_id)) = cmd.get(OPEN_ATTACHMENT) {
+        run_file_action(ctx, data, *message_id, *attachment_id, FileAction::Open);
+    } else if let Some(file) = cmd.get(SAVE_LOCATION_CHOSEN) {
+        if let Some(attachment) = attachment_choosing_save_location(data) {
+            attachment.transfer = match write_attachment(attachment, file.path()) {
+                Ok(()) => TransferState::Idle,
+                Err(err) => TransferState::Failed(err),
+            };
+        }
+    } else if cmd.is(SAVE_CANCELLED) {
+        if let Some(attachment) = attachment_choosing_save_location(data) {
+            attachment.transfer = TransferState::Idle;
+        }
+    } else {
+        return false;
+    }
+    ctx.set_handled();
+    true
+}
+
+/// Saves or opens the file, or asks the core for it first if it isn't downloaded yet.
+/// The core sends the command again once the download is done.
+fn run_file_action(ctx: &mut EventCtx, data: &mut AppState, message_id: u32, attachment_id: u32, action: FileAction) {
+    let attachment = match data.get_attachment_mut(message_id, attachment_id) {
+        Some(attachment) => attachment,
+        None => return,
+    };
+    if matches!(attachment.transfer, TransferState::Downloading { .. } | TransferState::ChoosingSaveLocation) {
+        // Already busy with it
+        return;
+    }
+    if attachment.bytes.is_none() {
+        if !CORE_SUPPORT.download_attachments {
+            attachment.transfer = TransferState::Failed("Downloading files isn't supported yet".to_string());
+            return;
+        }
+        attachment.transfer = TransferState::Downloading { progress: 0.0, then: action };
+        ctx.submit_command(SEND_TO_CORE.with(CoreRequest::DownloadAttachment { message_id, attachment_id }));
+        return;
+    }
+    match action {
+        FileAction::Save => {
+            attachment.transfer = TransferState::ChoosingSaveLocation;
+            ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(
+                FileDialogOptions::new()
+                    .default_name(attachment.file_name.clone())
+                    .accept_command(SAVE_LOCATION_CHOSEN)
+                    .cancel_command(SAVE_CANCELLED)
+            ));
+        },
+        FileAction::Open => {
+            // Opened from a temporary copy, in a folder per message so the name can be kept
+            let path = std::env::temp_dir()
+                .join("polychat")
+                .join(message_id.to_string())
+                .join(&attachment.file_name);
+            let result = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+                .map_err(|err| err.to_string())
+                .and_then(|_| write_attachment(attachment, &path))
+                .and_then(|_| opener::open(&path).map_err(|err| err.to_string()));
+            attachment.transfer = match result {
+                Ok(()) => TransferState::Idle,
+                Err(err) => {
+                    error!("Failed to open attachment {}: {}", attachment.file_name, err);
+                    TransferState::Failed(err)
+                }
+            };
+        },
+    }
+}
+
+fn write_attachment(attachment: &Attachment, path: &Path) -> Result<(), String> {
+    match &attachment.bytes {
+        Some(bytes) => std::fs::write(path, bytes.as_slice()).map_err(|err| err.to_string()),
+        None => Err("The file hasn't been downloaded".to_string()),
+    }
+}
+
+/// Only one save dialog is open at a time, so this is the one it's for
+fn attachment_choosing_save_location(data: &mut AppState) -> Option<&mut Attachment> {
+    let (message_id, attachment_id) = data.timeline_data.iter()
+        .flat_map(|group| group.messages.iter())
+        .find_map(|message| message.attachments.iter()
+            .find(|attachment| attachment.transfer == TransferState::ChoosingSaveLocation)
+            .map(|attachment| (message.id, attachment.id)))?;
+    data.get_attachment_mut(message_id, attachment_id)
+}
+
+/// Adds the image on the clipboard to the composer. Returns false if there isn't one,
+/// so the paste can go to the text box instead.
+pub(crate) fn paste_image_from_clipboard(ctx: &mut EventCtx) -> bool {
+    let clipboard = Application::global().clipboard();
+    let image_bytes = clipboard.preferred_format(CLIPBOARD_PNG_FORMATS)
+        .and_then(|format| clipboard.get_format(format));
+    match image_bytes {
+        Some(image_bytes) => {
+            add_in_background(ctx, move || vec![Attachment::new(PASTED_IMAGE_NAME.to_string(), image_bytes)]);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Reading and decoding can take a while, so it's done off the UI thread
+fn load_files_in_background(ctx: &mut EventCtx, paths: Vec<PathBuf>) {
+    add_in_background(ctx, move || {
+        paths.into_iter().filter_map(|path| {
+            let file_name = path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string_lossy().to_string());
+            match std::fs::read(&path) {
+                Ok(bytes) => Some(Attachment::new(file_name, bytes)),
+                Err(err) => {
+                    error!("Failed to read attachment {:?}: {}", path, err);
+                    None
+                }
+            }
+        }).collect()
+    });
+}
+
+fn add_in_background(ctx: &mut EventCtx, load: impl FnOnce() -> Vec<Attachment> + Send + 'static) {
+    let event_sink = ctx.get_external_handle();
+    std::thread::spawn(move || {
+        let attachments = load();
+        if !attachments.is_empty() {
+            event_sink.add_idle_callback(move |data: &mut AppState| {
+                data.pending_attachments.extend(attachments);
+            });
+        }
+    });
+}
+
+/// Passes Cmd+V to the attachments when the clipboard has an image
+pub(crate) struct PasteImageController;
+
+impl<W: Widget<AppState>> widget::Controller<AppState, W> for PasteImageController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &druid::Event, data: &mut AppState, env: &druid::Env) {
+        let is_paste = match event {
+            druid::Event::KeyDown(key) => HotKey::new(SysMods::Cmd, "v").matches(key),
+            druid::Event::Command(cmd) => cmd.is(druid::commands::PASTE),
+            _ => false,
+        };
+        if is_paste && paste_image_from_clipboard(ctx) {
+            ctx.set_handled();
+            return;
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// The attachments waiting to be sent, in a row above the text box. Takes no space when there are none.
+pub(crate) fn build_attachment_tray() -> impl Widget<AppState> {
+    widget::Either::new(
+        |data: &AppState, _env: &_| data.pending_attachments.is_empty(),
+        widget::SizedBox::empty(),
+        widget::Scroll::new(
+            widget::List::new(build_tray_item)
+                .horizontal()
+                .with_spacing(4.0)
+                .padding(4.0)
+        )
+        .horizontal()
+        .lens(AppState::pending_attachments)
+        .background(crate::PANEL_BACKGROUND_KEY)
+    )
+}
+
+fn build_tray_item() -> impl Widget<Attachment> {
+    // Only rebuilt when the attachment changes, so the image isn't converted again on every update
+    let thumbnail = widget::ViewSwitcher::new(
+        |attachment: &Attachment, _env: &_| attachment.id,
+        |_id, attachment: &Attachment, _env: &_| match &attachment.image {
+            Some(image) => widget::Image::new((**image).clone())
+                .fill_mode(widget::FillStrat::Contain)
+                .boxed(),
+            None => widget::Label::new(file_extension(&attachment.file_name))
+                .center()
+                .boxed(),
+        }
+    )
+    .fix_size(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+    let details = widget::Flex::column()
+        .with_child(
+            widget::Label::new(|attachment: &Attachment, _env: &_| attachment.file_name.clone())
+                .with_line_break_mode(widget::LineBreaking::Clip)
+                .fix_width(TRAY_ITEM_NAME_WIDTH)
+        )
+        .with_child(
+            widget::Label::new(|attachment: &Attachment, _env: &_| helper_functions::format_file_size(attachment.size_in_bytes))
+                .with_text_size(11.0)
+                .fix_width(TRAY_ITEM_NAME_WIDTH)
+        )
+        .cross_axis_alignment(widget::CrossAxisAlignment::Start);
+
+    widget::Flex::row()
+        .with_child(thumbnail)
+        .with_spacing(4.0)
+        .with_child(details)
+        .with_child(
+            widget::Button::new("✕").on_click(|ctx: &mut EventCtx, attachment: &mut Attachment, _env: &_| {
+                ctx.submit_command(REMOVE_PENDING_ATTACHMENT.with(attachment.id));
+            })
+        )
+        .padding(4.0)
+        .border(druid::theme::BORDER_DARK, 1.0)
+        .rounded(4.0)
+}
+
+/// Shown instead of a thumbnail for files that aren't images
+fn file_extension(file_name: &str) -> String {
+    match file_name.rsplit_once('.') {
+        Some((_, extension)) if !extension.is_empty() => extension.to_uppercase(),
+        _ => "FILE".to_string(),
+    }
+}
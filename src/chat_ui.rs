@@ -1,14 +1,21 @@
-use druid::Widget;
+use druid::{EventCtx, Widget, WidgetExt, WindowDesc};
 use druid::widget;
 use crate::AppState;
 use crate::widgets::chat_window_widget;
 
 pub(crate) fn build_chat_ui() -> impl Widget<AppState> {
-    let chat_widget = chat_window_widget::ChatWindowWidget::new();
+    let chat_widget = chat_window_widget::ChatWindowWidget::new()
+        .background(druid::theme::WINDOW_BACKGROUND_COLOR);
     widget::EnvScope::new(
         |env: &mut druid::env::Env, data: &AppState| {
-            data.layout_settings.set_env(env);
+            data.layout_settings.set_spacing_env(env);
+            data.layout_settings.theme.set_env(env);
         },
         chat_widget
     )
 }
+
+pub(crate) fn open_chat_window(ctx: &mut EventCtx) {
+    ctx.new_window(WindowDesc::new(build_chat_ui())
+        .window_size((300.0, 450.0)));
+}
@@ -0,0 +1,122 @@
+use druid::{im, Data, EventCtx, KbKey, Lens, LensExt, Widget, WidgetExt};
+use druid::widget;
+use crate::AppState;
+
+const COMPLETION_ROW_PADDING: f64 = 4.0;
+
+/// A list of completions for what's being typed in the composer, shown above it.
+/// The emoji shortcodes and the mentions each have one.
+pub(crate) struct CompletionList<T, C, S> {
+    /// The completions for what's being typed
+    pub completions: C,
+    /// The completion that Tab or Enter picks
+    pub selected: S,
+    /// What a completion's row shows
+    pub row_text: fn(&T) -> String,
+    /// Replaces what's being typed with the completion at the index
+    pub accept: fn(&mut AppState, usize),
+    /// Finds the completions again after the composer's text changed
+    pub update: fn(&mut AppState),
+}
+
+// Not derived, since that would need the completions themselves to be Copy
+impl<T, C: Copy, S: Copy> Clone for CompletionList<T, C, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, C: Copy, S: Copy> Copy for CompletionList<T, C, S> {}
+
+impl<T, C, S> CompletionList<T, C, S>
+where
+    T: Data,
+    C: Lens<AppState, im::Vector<T>> + Copy + 'static,
+    S: Lens<AppState, usize> + Copy + 'static,
+{
+    /// The completions in a column, with the selected one highlighted. Takes no space when there are none.
+    pub fn build(self) -> impl Widget<AppState> {
+        widget::ViewSwitcher::new(
+            move |data: &AppState, _env: &_| (self.completions.get(data), self.selected.get(data)),
+            move |(completions, selected): &(im::Vector<T>, usize), _data: &AppState, _env: &_| {
+                let mut list = widget::Flex::column().cross_axis_alignment(widget::CrossAxisAlignment::Fill);
+                for (index, completion) in completions.iter().enumerate() {
+                    list = list.with_child(self.build_row(completion, index, index == *selected));
+                }
+                list.background(crate::PANEL_BACKGROUND_KEY).boxed()
+            }
+        )
+    }
+
+    fn build_row(&self, completion: &T, index: usize, is_selected: bool) -> impl Widget<AppState> {
+        let accept = self.accept;
+        widget::Label::new((self.row_text)(completion))
+            .padding(COMPLETION_ROW_PADDING)
+            .expand_width()
+            .background(widget::Painter::new(move |ctx, data: &AppState, _env| {
+                if is_selected {
+                    ctx.fill(ctx.size().to_rect(), &data.layout_settings.theme.hover_highlight_color.to_druid_color());
+                }
+            }))
+            .on_click(move |_ctx: &mut EventCtx, data: &mut AppState, _env: &_| {
+                accept(data, index);
+            })
+    }
+
+    /// For the composer's text box
+    pub fn controller(self) -> CompletionController<T, C, S> {
+        CompletionController { list: self }
+    }
+}
+
+/// Finds the completions as the composer's text changes. While they're shown, Up and Down
+/// choose one, Tab or Enter picks it, and Escape hides them.
+pub(crate) struct CompletionController<T, C, S> {
+    list: CompletionList<T, C, S>,
+}
+
+impl<T, C, S, W> widget::Controller<AppState, W> for CompletionController<T, C, S>
+where
+    T: Data,
+    C: Lens<AppState, im::Vector<T>> + Copy + 'static,
+    S: Lens<AppState, usize> + Copy + 'static,
+    W: Widget<AppState>,
+{
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &druid::Event, data: &mut AppState, env: &druid::Env) {
+        let list = self.list;
+        if let druid::Event::KeyDown(key) = event {
+            let completion_count = list.completions.with(data, |completions| completions.len());
+            if completion_count > 0 {
+                let selected = list.selected.get(data);
+                let handled = match key.key {
+                    KbKey::ArrowDown => {
+                        list.selected.put(data, (selected + 1) % completion_count);
+                        true
+                    },
+                    KbKey::ArrowUp => {
+                        list.selected.put(data, (selected + completion_count - 1) % completion_count);
+                        true
+                    },
+                    KbKey::Tab | KbKey::Enter => {
+                        (list.accept)(data, selected);
+                        true
+                    },
+                    KbKey::Escape => {
+                        list.completions.with_mut(data, |completions| completions.clear());
+                        true
+                    },
+                    _ => false,
+                };
+                if handled {
+                    ctx.set_handled();
+                    return;
+                }
+            }
+        }
+        let old_text = data.text_edit.clone();
+        child.event(ctx, event, data, env);
+        if !old_text.same(&data.text_edit) {
+            (list.update)(data);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use druid::{Widget, WidgetExt};
+use druid::{widget, lens};
+use druid::widget::prelude::*;
+use crate::AppState;
+use crate::chat_ui;
+use crate::data::conversation_data::ConversationData;
+use crate::helper::layout_settings::WithSettings;
+use crate::widgets::avatar_widget::AvatarWidget;
+
+const BADGE_TEXT_SIZE: f64 = 11.0;
+const BADGE_PADDING: (f64, f64) = (6.0, 1.0);
+
+pub(crate) fn build_conversation_ui() -> impl Widget<AppState> {
+    widget::List::new(|| {
+        widget::Flex::row()
+            .with_child(AvatarWidget::new())
+            .with_default_spacer()
+            .with_flex_child(
+                widget::Label::new(|(_, conversation): &WithSettings<ConversationData>, _env: &_| {
+                    conversation.name.clone()
+                })
+                .with_line_break_mode(widget::LineBreaking::WordWrap)
+            , 1.0)
+            .with_child(build_unread_badge(|conversation| conversation.unread_mention_count, "@", true))
+            .with_spacer(2.0)
+            .with_child(build_unread_badge(|conversation| conversation.unread_count, "", false))
+            .padding(4.0)
+            .background(crate::PANEL_BACKGROUND_KEY)
+            .border(druid::theme::BORDER_DARK, 1.0)
+            .padding(1.0)
+            .on_click(|ctx: &mut EventCtx, (_, conversation): &mut WithSettings<ConversationData>, _| {
+                conversation.unread_count = 0;
+                conversation.unread_mention_count = 0;
+                chat_ui::open_chat_window(ctx);
+            })
+    })
+    // Pair the conversations with the settings so the avatars can use the picture settings
+    .lens(lens::Map::new(
+        |data: &AppState| (data.layout_settings.clone(), data.conversations.clone()),
+        |data: &mut AppState, (_, conversations): WithSettings<druid::im::Vector<ConversationData>>| {
+            data.conversations = conversations
+        },
+    ))
+}
+
+/// A count of unread messages in a rounded badge, hidden when there are none.
+/// Mentions stand out in the accent color.
+fn build_unread_badge(
+    count: fn(&ConversationData) -> u32,
+    prefix: &'static str,
+    is_mention: bool,
+) -> impl Widget<WithSettings<ConversationData>> {
+    widget::Either::new(
+        move |(_, conversation): &WithSettings<ConversationData>, _env: &_| count(conversation) > 0,
+        widget::Label::new(move |(_, conversation): &WithSettings<ConversationData>, _env: &_| {
+            format!("{}{}", prefix, count(conversation))
+        })
+        .with_text_size(BADGE_TEXT_SIZE)
+        .with_text_color(druid::Color::WHITE)
+        .padding(BADGE_PADDING)
+        .background(widget::Painter::new(move |ctx, (settings, _): &WithSettings<ConversationData>, _env| {
+            let color = if is_mention {
+                settings.theme.accent_color.to_druid_color()
+            } else {
+                settings.datetime_color.to_druid_color()
+            };
+            let rect = ctx.size().to_rect();
+            ctx.fill(rect.to_rounded_rect(rect.height() / 2.0), &color);
+        })),
+        widget::SizedBox::empty(),
+    )
+}
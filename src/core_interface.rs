@@ -1,8 +1,79 @@
 use std::path::PathBuf;
+use std::sync::mpsc;
 
+use log::{debug, warn};
 use polychat_ipc::{core::{ui_interface::ui_trait, ui_interface::load_status::LoadStatus}, api::schema::protocol::InitDataInstruction};
 
-use crate::data::{plugin_item_data::PluginItemData, app_state_data::AppState};
+use crate::data::{plugin_item_data::PluginItemData, app_state_data::{AppState, SELF_USER_ID}, avatar_data::Avatar};
+use crate::data::{conversation_data::ConversationData, presence_data::Presence, reaction_data::Reaction};
+use crate::data::attachment_data::{Attachment, FileAction, TransferState};
+use crate::attachment_ui;
+use crate::helper::mentions;
+
+/// Sent by widgets to have the delegate pass a request on to the core thread
+pub(crate) const SEND_TO_CORE: druid::Selector<CoreRequest> = druid::Selector::new("polysoft.druid-demo.send_to_core");
+
+/// Something the user did that the core has to carry out
+#[derive(Clone, Debug)]
+pub(crate) enum CoreRequest {
+    /// A message from the composer, with its attachments. The id is the one it has in our timeline.
+    SendMessage { message_id: u32, text: String, reply_to: Option<u32>, attachments: Vec<Attachment> },
+    EditMessage { message_id: u32, new_text: String },
+    DeleteMessage { message_id: u32 },
+    /// Gets the contents of a file that wasn't sent with its message
+    DownloadAttachment { message_id: u32, attachment_id: u32 },
+    /// Adds our reaction with the emoji, or removes it if we already reacted with it
+    ToggleReaction { message_id: u32, emoji: String },
+    /// Whether we're typing in the composer. Debounced, so it's only sent now and then.
+    SetTyping { is_typing: bool },
+}
+
+/// Which of the requests the core can carry out. The UI hides the actions for the others,
+/// and the delegate drops them. The core's API doesn't have any of them yet, so each one
+/// is turned on as it's added there.
+pub(crate) const CORE_SUPPORT: CoreSupport = CoreSupport {
+    send_messages: false,
+    edit_messages: false,
+    delete_messages: false,
+    reactions: false,
+    download_attachments: false,
+    typing_notifications: false,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CoreSupport {
+    /// Sending the composer's text and attachments. Until it's on, the composer only works
+    /// in the UI: sent messages and their attachments are shown in our timeline, but nobody
+    /// else gets them.
+    pub send_messages: bool,
+    pub edit_messages: bool,
+    pub delete_messages: bool,
+    pub reactions: bool,
+    pub download_attachments: bool,
+    pub typing_notifications: bool,
+}
+
+impl CoreSupport {
+    pub fn supports(&self, request: &CoreRequest) -> bool {
+        match request {
+            CoreRequest::SendMessage { .. } => self.send_messages,
+            CoreRequest::EditMessage { .. } => self.edit_messages,
+            CoreRequest::DeleteMessage { .. } => self.delete_messages,
+            CoreRequest::ToggleReaction { .. } => self.reactions,
+            CoreRequest::DownloadAttachment { .. } => self.download_attachments,
+            CoreRequest::SetTyping { .. } => self.typing_notifications,
+        }
+    }
+}
+
+/// Handles the requests from the UI until every sender is dropped, which happens when the UI closes.
+/// Only the ones in `CORE_SUPPORT` get here.
+pub(crate) fn handle_core_requests(requests: mpsc::Receiver<CoreRequest>) {
+    for request in requests {
+        // Nothing in CORE_SUPPORT is on yet. Each request is passed on to the core here as it's turned on.
+        warn!("Request {:?} reached the core thread, but the core can't carry it out.", request);
+    }
+}
 
 /**
  * This struct stores information to allow the core GUI interface
@@ -19,6 +90,193 @@ impl CoreInterface {
     }
 }
 
+/// The events the UI handles besides the ones in `ui_trait::GUI`. The core's GUI trait
+/// doesn't carry users, messages, attachments or conversations yet, so nothing calls these
+/// until it does. Each one is ready to be called from the trait impl at that point.
+#[allow(dead_code)]
+impl CoreInterface {
+    /// Called when a plugin delivers a user's profile picture as PNG or JPEG bytes.
+    pub fn on_user_avatar(&self, user_id: u32, image_bytes: Vec<u8>) {
+        debug!("on_user_avatar called for user {user_id} with {} bytes.", image_bytes.len());
+        // Decode on this thread so large pictures don't stall the UI
+        if let Some(avatar) = Avatar::from_image_bytes(&image_bytes) {
+            self.event_sink.add_idle_callback(move |data: &mut AppState| {
+                data.set_avatar(user_id, avatar);
+            });
+        }
+    }
+
+    /// Called when a plugin delivers a user's display name.
+    /// Users without a picture get their initials as their avatar.
+    pub fn on_user_display_name(&self, user_id: u32, display_name: String) {
+        debug!("on_user_display_name called for user {user_id} with name {display_name}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.set_display_name(user_id, &display_name);
+        });
+    }
+
+    /// Called when a protocol reports that a user's presence changed.
+    pub fn on_user_presence(&self, user_id: u32, presence: Presence) {
+        debug!("on_user_presence called for user {user_id} with presence {:?}.", presence);
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.set_presence(user_id, presence);
+        });
+    }
+
+    /// Called when the reactions on a message change, with all of its reactions.
+    pub fn on_message_reactions(&self, message_id: u32, reactions: Vec<Reaction>) {
+        debug!("on_message_reactions called for message {message_id} with {} reactions.", reactions.len());
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.set_reactions(message_id, reactions.into());
+        });
+    }
+
+    /// Called when the files sent with a message are downloaded, with each file's name and contents.
+    pub fn on_message_attachments(&self, message_id: u32, files: Vec<(String, Vec<u8>)>) {
+        debug!("on_message_attachments called for message {message_id} with {} files.", files.len());
+        // Decode on this thread so large images don't stall the UI
+        let attachments: Vec<Attachment> = files.into_iter()
+            .map(|(file_name, bytes)| Attachment::new(file_name, bytes))
+            .collect();
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            if let Some(message) = data.get_message_mut(message_id) {
+                message.attachments = attachments.into();
+            }
+        });
+    }
+
+    /// Called for each file sent with a message that the core only downloads when asked to.
+    pub fn on_message_file(&self, message_id: u32, attachment_id: u32, file_name: String, mime_type: String, size_in_bytes: usize) {
+        debug!("on_message_file called for message {message_id} with file {file_name}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            if let Some(message) = data.get_message_mut(message_id) {
+                message.attachments.push_back(Attachment::not_downloaded(attachment_id, file_name, mime_type, size_in_bytes));
+            }
+        });
+    }
+
+    /// Called as a download asked for with `CoreRequest::DownloadAttachment` goes, with the progress from 0 to 1.
+    pub fn on_attachment_download_progress(&self, message_id: u32, attachment_id: u32, progress: f64) {
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            if let Some(attachment) = data.get_attachment_mut(message_id, attachment_id) {
+                if let TransferState::Downloading { then, .. } = attachment.transfer {
+                    attachment.transfer = TransferState::Downloading { progress, then };
+                }
+            }
+        });
+    }
+
+    /// Called when a download is done. Carries on with saving or opening the file.
+    pub fn on_attachment_downloaded(&self, message_id: u32, attachment_id: u32, bytes: Vec<u8>) {
+        debug!("on_attachment_downloaded called for attachment {attachment_id} of message {message_id} with {} bytes.", bytes.len());
+        let event_sink = self.event_sink.clone();
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            if let Some(attachment) = data.get_attachment_mut(message_id, attachment_id) {
+                attachment.size_in_bytes = bytes.len();
+                attachment.bytes = Some(std::sync::Arc::new(bytes));
+                let next_action = match attachment.transfer {
+                    TransferState::Downloading { then, .. } => Some(then),
+                    _ => None,
+                };
+                attachment.transfer = TransferState::Idle;
+                let selector = match next_action {
+                    Some(FileAction::Save) => attachment_ui::SAVE_ATTACHMENT,
+                    Some(FileAction::Open) => attachment_ui::OPEN_ATTACHMENT,
+                    None => return,
+                };
+                let result = event_sink.submit_command(selector, (message_id, attachment_id), druid::Target::Auto);
+                if result.is_err() {
+                    warn!("Failed to continue with attachment {attachment_id}. Err: {:?}", result.err());
+                }
+            }
+        });
+    }
+
+    /// Called when a download failed. The error is shown on the file's card.
+    pub fn on_attachment_download_failed(&self, message_id: u32, attachment_id: u32, error: String) {
+        debug!("on_attachment_download_failed called for attachment {attachment_id} of message {message_id}: {error}");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            if let Some(attachment) = data.get_attachment_mut(message_id, attachment_id) {
+                attachment.transfer = TransferState::Failed(error);
+            }
+        });
+    }
+
+    /// Called when a message's text was changed by its sender.
+    pub fn on_message_edited(&self, message_id: u32, new_text: String, edited_at_epoch_seconds: i64) {
+        debug!("on_message_edited called for message {message_id}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.edit_message(message_id, new_text, edited_at_epoch_seconds);
+        });
+    }
+
+    /// Called when the core couldn't save our edit of a message.
+    pub fn on_message_edit_failed(&self, message_id: u32) {
+        debug!("on_message_edit_failed called for message {message_id}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.fail_pending_edit(message_id);
+        });
+    }
+
+    /// Called when a message was deleted. It's kept as a placeholder in the timeline.
+    pub fn on_message_deleted(&self, message_id: u32) {
+        debug!("on_message_deleted called for message {message_id}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.delete_message(message_id);
+        });
+    }
+
+    /// Called when someone in the open conversation starts or stops typing.
+    pub fn on_user_typing(&self, user_id: u32, is_typing: bool) {
+        debug!("on_user_typing called for user {user_id} with {is_typing}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.set_typing(user_id, is_typing);
+        });
+    }
+
+    /// Called with everyone in the open conversation, who can be mentioned from the composer.
+    pub fn on_conversation_members(&self, user_ids: Vec<u32>) {
+        debug!("on_conversation_members called with {} members.", user_ids.len());
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            data.set_conversation_members(user_ids.into());
+        });
+    }
+
+    /// Called when a message arrives in a conversation that isn't open. Counts it as unread,
+    /// separately if it mentions us.
+    pub fn on_conversation_message_unread(&self, conversation_id: u32, text: String) {
+        debug!("on_conversation_message_unread called for conversation {conversation_id}.");
+        let mentions_self = mentions::mentions_user(&text, SELF_USER_ID);
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            let index = data.conversations.iter().position(|conversation| conversation.conversation_id == conversation_id);
+            if let Some(conversation) = index.and_then(|index| data.conversations.get_mut(index)) {
+                if mentions_self {
+                    conversation.unread_mention_count += 1;
+                } else {
+                    conversation.unread_count += 1;
+                }
+            }
+        });
+    }
+
+    /// Called when a direct conversation with a user becomes available.
+    pub fn on_conversation_added(&self, conversation_id: u32, name: String, user_id: u32) {
+        debug!("on_conversation_added called with conversation {conversation_id} with user {user_id}.");
+        self.event_sink.add_idle_callback(move |data: &mut AppState| {
+            let conversation = ConversationData {
+                conversation_id,
+                name,
+                user_id,
+                avatar: data.get_avatar(user_id),
+                presence: data.get_presence(user_id),
+                unread_count: 0,
+                unread_mention_count: 0,
+            };
+            data.conversations.push_back(conversation);
+        });
+    }
+}
+
 /**
  * CoreInterface implements the trait GUI to get information
  * from the core.
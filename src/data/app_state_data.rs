@@ -1,33 +1,613 @@
 use std::sync;
-use druid::im;
-use druid::ImageBuf;
+use druid::{im, Data};
 
+use crate::helper::emoji_data;
 use crate::helper::layout_settings::LayoutSettings;
+use crate::helper::mentions;
+use crate::helper::message_formatting;
 
+use super::attachment_data::Attachment;
+use super::avatar_data::Avatar;
+use super::conversation_data::ConversationData;
+use super::emoji_picker_data::{EmojiPickerState, ShortcodeCompletion};
+use super::mention_data::Mention;
 use super::plugin_item_data::PluginItemData;
+use super::presence_data::Presence;
+use super::reaction_data::Reaction;
 
 #[derive(Clone, druid::Data, druid::Lens)]
 pub struct AppState {
     pub text_edit: sync::Arc<String>,
     pub timeline_data: im::Vector<MessageGroup>,
-    pub profile_pics: im::Vector<ImageBuf>,
-    pub layout_settings: LayoutSettings,
+    /// The avatar of every user we know about. Groups hold clones of these.
+    pub avatars: im::HashMap<u32, Avatar>,
+    /// The presence of every user that a protocol reported it for
+    pub presences: im::HashMap<u32, Presence>,
+    /// Names reported by the protocols. Users without one are shown by their id.
+    pub display_names: im::HashMap<u32, String>,
+    pub conversations: im::Vector<ConversationData>,
+    /// The message the composer is replying to, shown in a banner above it
+    pub replying_to: Option<ReplyReference>,
+    /// Files waiting in the composer, sent with the next message
+    pub pending_attachments: im::Vector<Attachment>,
+    pub emoji_picker: EmojiPickerState,
+    /// Emoji for the shortcode at the end of the composer's text, shown in a list above it
+    pub shortcode_completions: im::Vector<ShortcodeCompletion>,
+    /// The completion that Tab or Enter picks
+    pub selected_completion: usize,
+    /// Members whose name matches the one being typed after an `@`, shown in a list above the composer
+    pub mention_completions: im::Vector<Mention>,
+    /// The member that Tab or Enter picks
+    pub selected_mention: usize,
+    /// Members picked from the mention list since the last message was sent
+    pub composer_mentions: im::Vector<Mention>,
+    /// The members of the conversation that the protocol reported. Everyone who wrote in it can be mentioned too.
+    pub conversation_members: im::Vector<u32>,
+    /// Others typing in the conversation, in the order they started
+    pub typing_users: im::Vector<u32>,
+    /// Shared snapshot of the settings. Only replaced when a setting changes.
+    pub layout_settings: sync::Arc<LayoutSettings>,
     pub settings_open: bool,
     pub plugin_load_status: String,
     pub plugin_load_dir: Option<String>,
     pub plugin_list: im::Vector<PluginItemData>,
 }
 
+/// The user id used for messages sent by us
+pub const SELF_USER_ID: u32 = 0;
+
 #[derive(Clone, druid::Data, druid::Lens)]
 pub struct MessageGroup {
     pub user_id: u32,
-    pub profile_pic: ImageBuf,
+    /// The sender's name from the display names, kept up to date like the avatar
+    pub display_name: String,
+    pub avatar: Avatar,
+    pub presence: Presence,
     pub messages: im::Vector<Message>,
 }
 
+impl MessageGroup {
+    pub fn is_self_user(&self) -> bool {
+        self.user_id == SELF_USER_ID
+    }
+
+    /// Groups of only emoji-only messages are shown without a bubble
+    pub fn is_emoji_only(&self) -> bool {
+        !self.messages.is_empty() && self.messages.iter().all(Message::is_emoji_only)
+    }
+}
+
+impl AppState {
+    pub fn get_avatar(&self, user_id: u32) -> Avatar {
+        self.avatars.get(&user_id).cloned().unwrap_or_default()
+    }
+
+    /// Stores the avatar, and updates every group and conversation from the user.
+    pub fn set_avatar(&mut self, user_id: u32, avatar: Avatar) {
+        self.avatars.insert(user_id, avatar.clone());
+        update_where(&mut self.timeline_data,
+            |group| group.user_id == user_id && !group.avatar.same(&avatar),
+            |group| group.avatar = avatar.clone());
+        update_where(&mut self.conversations,
+            |conversation| conversation.user_id == user_id && !conversation.avatar.same(&avatar),
+            |conversation| conversation.avatar = avatar.clone());
+    }
+
+    pub fn get_presence(&self, user_id: u32) -> Presence {
+        self.presences.get(&user_id).copied().unwrap_or_default()
+    }
+
+    /// Stores the presence, and updates every group and conversation from the user.
+    pub fn set_presence(&mut self, user_id: u32, presence: Presence) {
+        self.presences.insert(user_id, presence);
+        update_where(&mut self.timeline_data,
+            |group| group.user_id == user_id && group.presence != presence,
+            |group| group.presence = presence);
+        update_where(&mut self.conversations,
+            |conversation| conversation.user_id == user_id && conversation.presence != presence,
+            |conversation| conversation.presence = presence);
+    }
+
+    pub fn get_display_name(&self, user_id: u32) -> String {
+        self.display_names.get(&user_id).cloned()
+            .unwrap_or_else(|| format!("User{}", user_id))
+    }
+
+    pub fn get_message(&self, message_id: u32) -> Option<&Message> {
+        self.timeline_data.iter()
+            .find_map(|group| group.messages.iter().find(|message| message.id == message_id))
+    }
+
+    /// Only copies the group and message being changed, since they're found before borrowing mutably
+    pub fn get_message_mut(&mut self, message_id: u32) -> Option<&mut Message> {
+        let (group_index, message_index) = self.timeline_data.iter().enumerate().find_map(|(group_index, group)| {
+            group.messages.iter().position(|message| message.id == message_id)
+                .map(|message_index| (group_index, message_index))
+        })?;
+        self.timeline_data.get_mut(group_index)?.messages.get_mut(message_index)
+    }
+
+    pub fn get_attachment_mut(&mut self, message_id: u32, attachment_id: u32) -> Option<&mut Attachment> {
+        let attachments = &mut self.get_message_mut(message_id)?.attachments;
+        let index = attachments.iter().position(|attachment| attachment.id == attachment_id)?;
+        attachments.get_mut(index)
+    }
+
+    /// Keeps the old text in the message's edit history. Also confirms our pending edit.
+    pub fn edit_message(&mut self, message_id: u32, new_text: String, edited_at_epoch_seconds: i64) {
+        let mention_names = self.mention_names(&new_text);
+        if let Some(message) = self.get_message_mut(message_id) {
+            message.pending_edit = None;
+            message.mention_names = mention_names;
+            let previous_text = std::mem::replace(&mut message.message, new_text);
+            message.edits.push_back(MessageEdit { previous_text, edited_at_epoch_seconds });
+        }
+    }
+
+    /// Drops our pending edit when the core couldn't save it, and opens the editor again
+    /// with its text, so it isn't lost and can be saved again.
+    pub fn fail_pending_edit(&mut self, message_id: u32) {
+        let has_pending_edit = self.get_message(message_id).is_some_and(|message| message.pending_edit.is_some());
+        if !has_pending_edit {
+            return;
+        }
+        self.stop_editing();
+        if let Some(message) = self.get_message_mut(message_id) {
+            message.edit_draft = message.pending_edit.take().map(sync::Arc::new);
+        }
+    }
+
+    /// Turns the message into a placeholder. Its text, history and reactions are dropped.
+    pub fn delete_message(&mut self, message_id: u32) {
+        if let Some(message) = self.get_message_mut(message_id) {
+            message.deleted = true;
+            message.message.clear();
+            message.edits.clear();
+            message.reactions.clear();
+            message.attachments.clear();
+            message.edit_draft = None;
+            message.pending_edit = None;
+        }
+    }
+
+    /// Opens the inline editor on one of our messages, closing any other one
+    pub fn start_editing(&mut self, message_id: u32) {
+        self.stop_editing();
+        if let Some(message) = self.get_message_mut(message_id) {
+            if message.is_own() && !message.deleted {
+                message.edit_draft = Some(sync::Arc::new(message.displayed_text().to_string()));
+            }
+        }
+    }
+
+    /// Replaces the reactions on the message with the ones the core reported
+    pub fn set_reactions(&mut self, message_id: u32, reactions: im::Vector<Reaction>) {
+        if let Some(message) = self.get_message_mut(message_id) {
+            message.reactions = reactions;
+        }
+    }
+
+    pub fn remove_pending_attachment(&mut self, attachment_id: u32) {
+        self.pending_attachments.retain(|attachment| attachment.id != attachment_id);
+    }
+
+    /// Adds the emoji to the end of the composer's text
+    pub fn insert_emoji(&mut self, emoji: &str) {
+        sync::Arc::make_mut(&mut self.text_edit).push_str(emoji);
+        self.emoji_picker.record_use(emoji);
+        self.update_shortcode_completions();
+    }
+
+    /// Finds the emoji for the shortcode being typed. Only needed when the composer's text changes.
+    pub fn update_shortcode_completions(&mut self) {
+        let completions: im::Vector<ShortcodeCompletion> = emoji_data::shortcode_being_typed(&self.text_edit)
+            .map(|(_, partial)| emoji_data::complete_shortcode(partial))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(emoji, shortcode)| ShortcodeCompletion { emoji: emoji.to_string(), shortcode: shortcode.to_string() })
+            .collect();
+        if !completions.is_empty() || !self.shortcode_completions.is_empty() {
+            self.shortcode_completions = completions;
+            self.selected_completion = 0;
+        }
+    }
+
+    /// Replaces the shortcode being typed with the completion's emoji
+    pub fn accept_shortcode_completion(&mut self, index: usize) {
+        let emoji = match self.shortcode_completions.get(index) {
+            Some(completion) => completion.emoji.clone(),
+            None => return,
+        };
+        if let Some((colon, _)) = emoji_data::shortcode_being_typed(&self.text_edit) {
+            let text = sync::Arc::make_mut(&mut self.text_edit);
+            text.truncate(colon);
+            text.push_str(&emoji);
+            self.emoji_picker.record_use(&emoji);
+        }
+        self.shortcode_completions.clear();
+    }
+
+    /// The current names of the users mentioned in the text, leaving out anyone who isn't
+    /// us, a member of the conversation, or in the timeline
+    pub fn mention_names(&self, text: &str) -> im::HashMap<u32, String> {
+        self.mention_names_among(text, &self.mentionable_users())
+    }
+
+    fn mention_names_among(&self, text: &str, known_users: &[u32]) -> im::HashMap<u32, String> {
+        mentions::mentioned_users(text)
+            .filter(|user_id| *user_id == SELF_USER_ID || known_users.binary_search(user_id).is_ok())
+            .map(|user_id| (user_id, self.get_display_name(user_id)))
+            .collect()
+    }
+
+    /// Looks up the mentioned names again after a name or the members change
+    fn refresh_mention_names(&mut self) {
+        let known_users = self.mentionable_users();
+        let changes: Vec<(usize, usize, im::HashMap<u32, String>)> = self.timeline_data.iter().enumerate()
+            .flat_map(|(group_index, group)| group.messages.iter().enumerate()
+                .map(move |(message_index, message)| (group_index, message_index, message)))
+            .filter_map(|(group_index, message_index, message)| {
+                let names = self.mention_names_among(&message.message, &known_users);
+                (names != message.mention_names).then_some((group_index, message_index, names))
+            })
+            .collect();
+        for (group_index, message_index, names) in changes {
+            if let Some(message) = self.timeline_data.get_mut(group_index).and_then(|group| group.messages.get_mut(message_index)) {
+                message.mention_names = names;
+            }
+        }
+    }
+
+    pub fn set_conversation_members(&mut self, user_ids: im::Vector<u32>) {
+        self.conversation_members = user_ids;
+        self.refresh_mention_names();
+    }
+
+    /// Everyone who can be mentioned in the conversation, except us
+    fn mentionable_users(&self) -> Vec<u32> {
+        let mut user_ids: Vec<u32> = self.conversation_members.iter().copied()
+            .chain(self.timeline_data.iter().map(|group| group.user_id))
+            .filter(|user_id| *user_id != SELF_USER_ID)
+            .collect();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        user_ids
+    }
+
+    /// Finds the members for the name being typed after an `@`. Only needed when the composer's text changes.
+    pub fn update_mention_completions(&mut self) {
+        let completions: im::Vector<Mention> = match mentions::mention_being_typed(&self.text_edit) {
+            Some((_, partial)) => {
+                let mut matches: Vec<Mention> = self.mentionable_users().into_iter()
+                    .map(|user_id| Mention { user_id, name: self.get_display_name(user_id) })
+                    .filter(|mention| mentions::name_matches(&mention.name, partial))
+                    .collect();
+                matches.sort_by_key(|mention| mention.name.to_lowercase());
+                matches.into_iter().take(mentions::MAX_MENTION_COMPLETIONS).collect()
+            },
+            None => im::Vector::new(),
+        };
+        if !completions.is_empty() || !self.mention_completions.is_empty() {
+            self.mention_completions = completions;
+            self.selected_mention = 0;
+        }
+    }
+
+    /// Replaces the `@` and the name being typed with the member's full name,
+    /// and remembers the mention so it's sent as one
+    pub fn accept_mention_completion(&mut self, index: usize) {
+        let mention = match self.mention_completions.get(index) {
+            Some(mention) => mention.clone(),
+            None => return,
+        };
+        if let Some((at, _)) = mentions::mention_being_typed(&self.text_edit) {
+            let text = sync::Arc::make_mut(&mut self.text_edit);
+            text.truncate(at);
+            text.push_str(&mentions::typed_mention(&mention.name));
+            if !self.composer_mentions.contains(&mention) {
+                self.composer_mentions.push_back(mention);
+            }
+        }
+        self.mention_completions.clear();
+    }
+
+    pub fn set_typing(&mut self, user_id: u32, is_typing: bool) {
+        let index = self.typing_users.index_of(&user_id);
+        match (index, is_typing) {
+            (None, true) => self.typing_users.push_back(user_id),
+            (Some(index), false) => {
+                self.typing_users.remove(index);
+            },
+            _ => {},
+        }
+    }
+
+    pub fn stop_editing(&mut self) {
+        update_where(&mut self.timeline_data,
+            |group| group.messages.iter().any(|message| message.edit_draft.is_some()),
+            |group| update_where(&mut group.messages,
+                |message| message.edit_draft.is_some(),
+                |message| message.edit_draft = None));
+    }
+
+    /// Our newest message that can still be edited
+    pub fn last_own_message_id(&self) -> Option<u32> {
+        self.timeline_data.iter().rev()
+            .flat_map(|group| group.messages.iter().rev())
+            .find(|message| message.is_own() && !message.deleted)
+            .map(|message| message.id)
+    }
+
+    pub fn make_reply_reference(&self, message_id: u32) -> Option<ReplyReference> {
+        let message = self.get_message(message_id)?;
+        Some(ReplyReference {
+            message_id,
+            sender_name: self.get_display_name(message.user_id),
+            first_line: message_formatting::to_plain_text(&message.message, &message.mention_names)
+                .lines().next().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// An id for a message sent from here. Follows the highest id in the timeline.
+    pub fn next_message_id(&self) -> u32 {
+        self.timeline_data.iter()
+            .flat_map(|group| group.messages.iter())
+            .map(|message| message.id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Stores the name, and updates every group from the user and every mention of them.
+    /// Also uses its initials as the avatar unless the user already has a picture.
+    pub fn set_display_name(&mut self, user_id: u32, display_name: &str) {
+        self.display_names.insert(user_id, display_name.to_string());
+        update_where(&mut self.timeline_data,
+            |group| group.user_id == user_id && group.display_name != display_name,
+            |group| group.display_name = display_name.to_string());
+        self.refresh_mention_names();
+        if !self.get_avatar(user_id).is_image() {
+            self.set_avatar(user_id, Avatar::from_display_name(user_id, display_name));
+        }
+    }
+}
+
+/// Changes only the items that need it. Going through `iter_mut` would copy every node
+/// of the vector that's shared with an older snapshot, even for items that don't change.
+fn update_where<T: Clone>(items: &mut im::Vector<T>, needs_update: impl Fn(&T) -> bool, mut update: impl FnMut(&mut T)) {
+    let indices: Vec<usize> = items.iter().enumerate()
+        .filter(|(_, item)| needs_update(item))
+        .map(|(index, _)| index)
+        .collect();
+    for index in indices {
+        if let Some(item) = items.get_mut(index) {
+            update(item);
+        }
+    }
+}
+
 #[derive(Clone, druid::Data)]
 pub struct Message {
+    pub id: u32,
+    /// The sender. Same as the group's user.
+    pub user_id: u32,
     pub message: String,
     pub position_in_group: u32,
     pub timestamp_epoch_seconds: i64,
-}
\ No newline at end of file
+    /// Shown as chips under the message, in the order they were first added
+    pub reactions: im::Vector<Reaction>,
+    pub reply_to: Option<ReplyReference>,
+    pub attachments: im::Vector<Attachment>,
+    /// Every edit so far, oldest first
+    pub edits: im::Vector<MessageEdit>,
+    /// Deleted messages stay in their group as a placeholder, so the grouping doesn't change
+    pub deleted: bool,
+    /// The text in the inline editor, while this message is being edited
+    pub edit_draft: Option<sync::Arc<String>>,
+    /// An edit that was sent to the core, shown until the core confirms it
+    pub pending_edit: Option<String>,
+    /// The names of the known users mentioned in the text. Only their tokens are shown
+    /// as mentions, so a token typed by hand can't pass for one.
+    pub mention_names: im::HashMap<u32, String>,
+}
+
+#[derive(Clone, druid::Data)]
+pub struct MessageEdit {
+    /// The text from before this edit. The first edit has the original text.
+    pub previous_text: String,
+    pub edited_at_epoch_seconds: i64,
+}
+
+/// The message a reply is for. Copied from the original when replying, so it can be
+/// shown without looking the original up, even if it isn't loaded.
+#[derive(Clone, druid::Data)]
+pub struct ReplyReference {
+    pub message_id: u32,
+    pub sender_name: String,
+    pub first_line: String,
+}
+
+impl Message {
+    /// Whether we sent it, which allows editing and deleting it
+    pub fn is_own(&self) -> bool {
+        self.user_id == SELF_USER_ID
+    }
+
+    /// The pending edit if there is one, or the message
+    pub fn displayed_text(&self) -> &str {
+        self.pending_edit.as_deref().unwrap_or(&self.message)
+    }
+
+    /// One to a few emoji and nothing else, which are shown large.
+    /// Messages with attachments keep their bubble, since the attachments are in it.
+    pub fn is_emoji_only(&self) -> bool {
+        !self.deleted
+            && self.attachments.is_empty()
+            && emoji_data::count_if_only_emoji(self.displayed_text())
+                .is_some_and(|count| (1..=emoji_data::MAX_LARGE_EMOJI).contains(&count))
+    }
+
+    /// Someone else's message that mentions us, which is highlighted
+    pub fn mentions_self(&self) -> bool {
+        !self.is_own() && !self.deleted && mentions::mentions_user(self.displayed_text(), SELF_USER_ID)
+    }
+
+    pub fn last_edited_at(&self) -> Option<i64> {
+        self.edits.last().map(|edit| edit.edited_at_epoch_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u32, user_id: u32, text: &str) -> Message {
+        Message {
+            id,
+            user_id,
+            message: text.to_string(),
+            position_in_group: 0,
+            timestamp_epoch_seconds: 0,
+            reactions: im::Vector::new(),
+            reply_to: None,
+            attachments: im::Vector::new(),
+            edits: im::Vector::new(),
+            deleted: false,
+            edit_draft: None,
+            pending_edit: None,
+            mention_names: im::HashMap::new(),
+        }
+    }
+
+    fn group(user_id: u32, messages: Vec<Message>) -> MessageGroup {
+        MessageGroup {
+            user_id,
+            display_name: format!("User{}", user_id),
+            avatar: Avatar::Default,
+            presence: Presence::Unknown,
+            messages: messages.into(),
+        }
+    }
+
+    /// Two of our messages, then one from someone else
+    fn state() -> AppState {
+        AppState {
+            text_edit: sync::Arc::new(String::new()),
+            timeline_data: im::vector![
+                group(SELF_USER_ID, vec![message(0, SELF_USER_ID, "Hello"), message(1, SELF_USER_ID, "Second")]),
+                group(1, vec![message(2, 1, "Hi there")]),
+            ],
+            avatars: im::HashMap::new(),
+            presences: im::HashMap::new(),
+            display_names: im::HashMap::new(),
+            conversations: im::Vector::new(),
+            replying_to: None,
+            pending_attachments: im::Vector::new(),
+            emoji_picker: EmojiPickerState::default(),
+            shortcode_completions: im::Vector::new(),
+            selected_completion: 0,
+            mention_completions: im::Vector::new(),
+            selected_mention: 0,
+            composer_mentions: im::Vector::new(),
+            conversation_members: im::Vector::new(),
+            typing_users: im::Vector::new(),
+            layout_settings: sync::Arc::new(LayoutSettings::default()),
+            settings_open: false,
+            plugin_load_status: String::new(),
+            plugin_load_dir: None,
+            plugin_list: im::Vector::new(),
+        }
+    }
+
+    fn draft(state: &AppState, message_id: u32) -> Option<String> {
+        state.get_message(message_id)?.edit_draft.as_ref().map(|draft| draft.to_string())
+    }
+
+    #[test]
+    fn editing_opens_one_editor_on_our_own_messages() {
+        let mut state = state();
+        state.start_editing(0);
+        assert_eq!(draft(&state, 0), Some("Hello".to_string()));
+
+        state.start_editing(1);
+        assert_eq!(draft(&state, 0), None);
+        assert_eq!(draft(&state, 1), Some("Second".to_string()));
+
+        state.start_editing(2);
+        assert!((0..3).all(|message_id| draft(&state, message_id).is_none()));
+    }
+
+    #[test]
+    fn edits_keep_the_old_text_and_confirm_the_pending_edit() {
+        let mut state = state();
+        state.get_message_mut(0).unwrap().pending_edit = Some("Hello!".to_string());
+        state.edit_message(0, "Hello!".to_string(), 100);
+
+        let message = state.get_message(0).unwrap();
+        assert_eq!(message.message, "Hello!");
+        assert_eq!(message.pending_edit, None);
+        assert_eq!(message.edits.len(), 1);
+        assert_eq!(message.edits[0].previous_text, "Hello");
+        assert_eq!(message.last_edited_at(), Some(100));
+    }
+
+    #[test]
+    fn failed_edits_reopen_the_editor_with_their_text() {
+        let mut state = state();
+        state.start_editing(1);
+        state.get_message_mut(0).unwrap().pending_edit = Some("Hello!".to_string());
+        state.fail_pending_edit(0);
+
+        let message = state.get_message(0).unwrap();
+        assert_eq!(message.message, "Hello");
+        assert_eq!(message.pending_edit, None);
+        assert_eq!(draft(&state, 0), Some("Hello!".to_string()));
+        assert_eq!(draft(&state, 1), None);
+    }
+
+    #[test]
+    fn failing_without_a_pending_edit_keeps_the_open_editor() {
+        let mut state = state();
+        state.start_editing(1);
+        state.fail_pending_edit(0);
+        assert_eq!(draft(&state, 0), None);
+        assert_eq!(draft(&state, 1), Some("Second".to_string()));
+    }
+
+    #[test]
+    fn deleted_messages_become_placeholders() {
+        let mut state = state();
+        state.start_editing(1);
+        state.edit_message(1, "Second, edited".to_string(), 100);
+        state.set_reactions(1, im::vector![
+            Reaction { emoji: "👍".to_string(), count: 1, reacted_by_self: false, user_ids: im::vector![1] },
+        ]);
+        state.delete_message(1);
+
+        let message = state.get_message(1).unwrap();
+        assert!(message.deleted);
+        assert!(message.message.is_empty() && message.edits.is_empty() && message.reactions.is_empty());
+        assert_eq!(draft(&state, 1), None);
+        // Can't be edited anymore
+        assert_eq!(state.last_own_message_id(), Some(0));
+        state.start_editing(1);
+        assert_eq!(draft(&state, 1), None);
+    }
+
+    #[test]
+    fn reactions_are_replaced_with_the_reported_ones() {
+        let mut state = state();
+        state.set_reactions(2, im::vector![
+            Reaction { emoji: "👍".to_string(), count: 2, reacted_by_self: true, user_ids: im::vector![SELF_USER_ID, 1] },
+        ]);
+        state.set_reactions(2, im::vector![
+            Reaction { emoji: "🎉".to_string(), count: 1, reacted_by_self: false, user_ids: im::vector![1] },
+        ]);
+
+        let reactions = &state.get_message(2).unwrap().reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "🎉");
+        assert!(!reactions[0].reacted_by_self);
+        // Other messages are left alone
+        assert!(state.get_message(0).unwrap().reactions.is_empty());
+    }
+}
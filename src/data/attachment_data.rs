@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use druid::ImageBuf;
+
+static NEXT_ATTACHMENT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A file sent along with a message. Cloning is cheap, the contents are shared.
+#[derive(Clone, druid::Data)]
+pub struct Attachment {
+    /// Tells the attachments of a message apart. Ours come from a counter, and others' from the core.
+    pub id: u32,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size_in_bytes: usize,
+    /// None until the core downloads the file, for files that weren't sent with the message
+    pub bytes: Option<Arc<Vec<u8>>>,
+    /// Set when the file could be decoded as an image
+    pub image: Option<Arc<ImageBuf>>,
+    pub transfer: TransferState,
+}
+
+/// What's happening with a file, shown on its card
+#[derive(Clone, Debug, PartialEq, druid::Data)]
+pub enum TransferState {
+    Idle,
+    /// Waiting for the core to download the file. The action runs once it's done.
+    Downloading { progress: f64, then: FileAction },
+    /// The save dialog is open for this file
+    ChoosingSaveLocation,
+    Failed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, druid::Data)]
+pub enum FileAction {
+    Save,
+    Open,
+}
+
+impl Attachment {
+    /// Tries to decode the file as an image. Slow for large files, so not for the UI thread.
+    pub fn new(file_name: String, bytes: Vec<u8>) -> Attachment {
+        let image = ImageBuf::from_data(&bytes).ok().map(Arc::new);
+        Attachment {
+            id: NEXT_ATTACHMENT_ID.fetch_add(1, Ordering::Relaxed),
+            mime_type: mime_type_for_file_name(&file_name).to_string(),
+            file_name,
+            size_in_bytes: bytes.len(),
+            bytes: Some(Arc::new(bytes)),
+            image,
+            transfer: TransferState::Idle,
+        }
+    }
+
+    /// A file the core knows about, but hasn't downloaded yet
+    pub fn not_downloaded(id: u32, file_name: String, mime_type: String, size_in_bytes: usize) -> Attachment {
+        Attachment { id, file_name, mime_type, size_in_bytes, bytes: None, image: None, transfer: TransferState::Idle }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.image.is_some()
+    }
+}
+
+// The contents are left out, they can be megabytes long
+impl std::fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Attachment")
+            .field("id", &self.id)
+            .field("file_name", &self.file_name)
+            .field("mime_type", &self.mime_type)
+            .field("size_in_bytes", &self.size_in_bytes)
+            .field("is_downloaded", &self.bytes.is_some())
+            .field("is_image", &self.is_image())
+            .finish()
+    }
+}
+
+/// A best guess from the extension, for files we send. Files from others come with theirs.
+pub fn mime_type_for_file_name(file_name: &str) -> &'static str {
+    let extension = file_name.rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "7z" => "application/x-7z-compressed",
+        "rar" => "application/vnd.rar",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "txt" | "log" | "md" => "text/plain",
+        "json" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use druid::ImageBuf;
+use tracing::error;
+
+use crate::helper::layout_settings::SimpleColor;
+
+/// What to draw as a user's profile picture.
+/// Cloning is cheap, so every group from a user shares the same decoded image.
+#[derive(Clone, druid::Data)]
+pub enum Avatar {
+    /// A picture delivered by a plugin
+    Image(Arc<ImageBuf>),
+    /// Initials of the display name, on a color picked from the user id
+    Initials { initials: String, color: SimpleColor },
+    /// The bundled default picture
+    Default,
+}
+
+impl Default for Avatar {
+    fn default() -> Self {
+        Avatar::Default
+    }
+}
+
+impl Avatar {
+    /// Decodes PNG or JPEG bytes. Returns None if they can't be decoded.
+    pub fn from_image_bytes(image_bytes: &[u8]) -> Option<Avatar> {
+        match ImageBuf::from_data(image_bytes) {
+            Ok(image) => Some(Avatar::Image(Arc::new(image))),
+            Err(err) => {
+                error!("Failed to decode avatar: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Up to two initials from the display name. Falls back to the default
+    /// picture if the name has nothing to make initials from.
+    pub fn from_display_name(user_id: u32, display_name: &str) -> Avatar {
+        let initials: String = display_name.split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .filter(|c| c.is_alphanumeric())
+            .take(2)
+            .flat_map(|c| c.to_uppercase())
+            .collect();
+        if initials.is_empty() {
+            Avatar::Default
+        } else {
+            Avatar::Initials { initials, color: user_color(user_id) }
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(self, Avatar::Image(_))
+    }
+}
+
+/// A color that's always the same for the same user
+pub fn user_color(user_id: u32) -> SimpleColor {
+    // Stepping by the golden angle keeps consecutive ids far apart on the color wheel
+    let hue = (user_id as f64 * 137.508) % 360.0;
+    SimpleColor::from_hsv(hue, 0.45, 0.7, 255)
+}
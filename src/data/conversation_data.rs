@@ -0,0 +1,17 @@
+use super::avatar_data::Avatar;
+use super::presence_data::Presence;
+
+/// A direct conversation with another user, as listed in the main window
+#[derive(Clone, druid::Data, druid::Lens)]
+pub struct ConversationData {
+    pub conversation_id: u32,
+    pub name: String,
+    /// The user on the other side of the conversation
+    pub user_id: u32,
+    pub avatar: Avatar,
+    pub presence: Presence,
+    /// Unread messages that don't mention us
+    pub unread_count: u32,
+    /// Unread messages that mention us, counted in their own badge
+    pub unread_mention_count: u32,
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use druid::im;
+
+/// Most recently used emoji kept for the picker
+const MAX_RECENT_EMOJI: usize = 32;
+
+#[derive(Clone, druid::Data, druid::Lens)]
+pub struct EmojiPickerState {
+    pub is_open: bool,
+    pub search: Arc<String>,
+    /// Index of the bundled category that's shown, or None for the recently used emoji
+    pub category: Option<usize>,
+    /// Newest first
+    pub recent: im::Vector<String>,
+    /// The message the picked emoji reacts to. When None, it goes into the composer instead.
+    pub reacting_to: Option<u32>,
+}
+
+impl Default for EmojiPickerState {
+    fn default() -> Self {
+        EmojiPickerState {
+            is_open: false,
+            search: Arc::new(String::new()),
+            // Starts on the first category, since nothing was used yet
+            category: Some(0),
+            recent: im::Vector::new(),
+            reacting_to: None,
+        }
+    }
+}
+
+impl EmojiPickerState {
+    /// Opens the picker to react to the message with the emoji picked
+    pub fn open_for_reaction(&mut self, message_id: u32) {
+        self.is_open = true;
+        self.reacting_to = Some(message_id);
+    }
+
+    /// Closes the picker. The next time it opens, it's for the composer again.
+    pub fn close(&mut self) {
+        self.is_open = false;
+        self.reacting_to = None;
+    }
+
+    /// Moves the emoji to the front of the recently used ones
+    pub fn record_use(&mut self, emoji: &str) {
+        self.recent.retain(|recent| recent != emoji);
+        self.recent.push_front(emoji.to_string());
+        self.recent.truncate(MAX_RECENT_EMOJI);
+    }
+}
+
+/// A suggestion for the shortcode being typed in the composer
+#[derive(Clone, druid::Data)]
+pub struct ShortcodeCompletion {
+    pub emoji: String,
+    pub shortcode: String,
+}
@@ -0,0 +1,7 @@
+/// A member picked from the mention list. Kept until the message is sent, when its
+/// `@name` in the composer's text becomes a mention token.
+#[derive(Clone, Debug, PartialEq, druid::Data)]
+pub struct Mention {
+    pub user_id: u32,
+    pub name: String,
+}
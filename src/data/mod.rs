@@ -1,2 +1,10 @@
 pub mod app_state_data;
-pub mod plugin_item_data;
\ No newline at end of file
+pub mod attachment_data;
+pub mod avatar_data;
+pub mod conversation_data;
+pub mod emoji_picker_data;
+pub mod mention_data;
+pub mod plugin_item_data;
+pub mod presence_data;
+pub mod reaction_data;
+pub mod sample_data;
\ No newline at end of file
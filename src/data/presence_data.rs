@@ -0,0 +1,31 @@
+use druid::Color;
+
+/// Whether a user is around, as reported by their protocol
+#[derive(Clone, Copy, PartialEq, Debug, druid::Data)]
+pub enum Presence {
+    Online,
+    Away,
+    Busy,
+    Offline,
+    /// The protocol hasn't told us. No status dot is shown.
+    Unknown,
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Presence::Unknown
+    }
+}
+
+impl Presence {
+    /// The color of the status dot, if one should be shown
+    pub fn get_dot_color(&self) -> Option<Color> {
+        match self {
+            Presence::Online => Some(Color::rgb8(67, 181, 129)),
+            Presence::Away => Some(Color::rgb8(250, 168, 26)),
+            Presence::Busy => Some(Color::rgb8(240, 71, 71)),
+            Presence::Offline => Some(Color::rgb8(116, 127, 141)),
+            Presence::Unknown => None,
+        }
+    }
+}
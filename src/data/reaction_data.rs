@@ -0,0 +1,11 @@
+use druid::im;
+
+/// One emoji reaction on a message, with everyone who reacted with it
+#[derive(Clone, Debug, druid::Data)]
+pub struct Reaction {
+    pub emoji: String,
+    /// Can be more than the known users, since some protocols only report a total
+    pub count: u32,
+    pub reacted_by_self: bool,
+    pub user_ids: im::Vector<u32>,
+}
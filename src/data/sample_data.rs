@@ -0,0 +1,73 @@
+use druid::im;
+
+use super::app_state_data::{Message, MessageGroup, ReplyReference, SELF_USER_ID};
+use super::avatar_data::Avatar;
+use super::presence_data::Presence;
+use super::reaction_data::Reaction;
+
+/// A short canned conversation for previewing the layout settings.
+/// Has messages from self and others, short and long messages, and multi-message groups.
+pub fn preview_conversation() -> im::Vector<MessageGroup> {
+    let start_epoch = chrono::offset::Local::now().timestamp() - 10 * 60;
+    let sam = Avatar::from_display_name(1, "Sam Rivera");
+    let conversation: [(u32, &str, Avatar, Presence, &[&str]); 5] = [
+        (1, "Sam Rivera", sam.clone(), Presence::Online, &["Hey, are we still on for tonight?"]),
+        (SELF_USER_ID, "You", Avatar::Default, Presence::Online, &["Yes!", "I was thinking we could try the new place downtown. It's supposed to have a really good patio, and it's only a short walk from the station."]),
+        (1, "Sam Rivera", sam, Presence::Online, &["Sounds great", "What time?"]),
+        (2, "Jo Park", Avatar::from_display_name(2, "Jo Park"), Presence::Away, &["Mind if I join? I'm getting off work early today."]),
+        (SELF_USER_ID, "You", Avatar::Default, Presence::Online, &["ok", "See you both at 7"]),
+    ];
+    let mut timeline = im::Vector::new();
+    for (group_index, (user_id, display_name, avatar, presence, texts)) in conversation.iter().enumerate() {
+        let mut messages = im::Vector::new();
+        for (message_index, text) in texts.iter().enumerate() {
+            messages.push_back(Message {
+                id: (group_index * 2 + message_index) as u32,
+                user_id: *user_id,
+                message: text.to_string(),
+                position_in_group: message_index as u32,
+                timestamp_epoch_seconds: start_epoch + (group_index * 120 + message_index * 20) as i64,
+                reactions: preview_reactions(group_index, message_index),
+                reply_to: preview_reply(group_index, message_index),
+                attachments: im::vector![],
+                edits: im::vector![],
+                deleted: false,
+                edit_draft: None,
+                pending_edit: None,
+                mention_names: im::HashMap::new(),
+            });
+        }
+        timeline.push_back(MessageGroup {
+            user_id: *user_id,
+            display_name: display_name.to_string(),
+            avatar: avatar.clone(),
+            presence: *presence,
+            messages,
+        });
+    }
+    timeline
+}
+
+/// A few reactions on the long message in the preview, one of them from us
+fn preview_reactions(group_index: usize, message_index: usize) -> im::Vector<Reaction> {
+    if (group_index, message_index) != (1, 1) {
+        return im::vector![];
+    }
+    im::vector![
+        Reaction { emoji: "👍".to_string(), count: 2, reacted_by_self: false, user_ids: im::vector![1, 2] },
+        Reaction { emoji: "😋".to_string(), count: 2, reacted_by_self: true, user_ids: im::vector![SELF_USER_ID, 1] },
+    ]
+}
+
+/// Jo's message replies to Sam asking what time
+fn preview_reply(group_index: usize, message_index: usize) -> Option<ReplyReference> {
+    if (group_index, message_index) != (3, 0) {
+        return None;
+    }
+    Some(ReplyReference {
+        message_id: 2 * 2 + 1,
+        sender_name: "Sam Rivera".to_string(),
+        first_line: "What time?".to_string(),
+    })
+}
+
@@ -0,0 +1,72 @@
+use druid::{im, EventCtx, Lens, LensExt, Widget, WidgetExt};
+use druid::widget;
+use crate::AppState;
+use crate::completion_ui::CompletionList;
+use crate::data::emoji_picker_data::{EmojiPickerState, ShortcodeCompletion};
+use crate::helper::emoji_data;
+use crate::widgets::emoji_grid_widget::EmojiGridWidget;
+
+pub(crate) const EMOJI_PICKER_SIZE: (f64, f64) = (320.0, 300.0);
+const RECENT_TAB_ICON: &str = "🕘";
+const TAB_TEXT_SIZE: f64 = 18.0;
+
+/// The picker shown over the chat window by the emoji button next to the send icon:
+/// a search box, a tab for the recently used emoji and for every category, and the emoji grid.
+pub(crate) fn build_emoji_picker() -> impl Widget<AppState> {
+    let mut tabs = widget::Flex::row()
+        .with_child(build_category_tab(RECENT_TAB_ICON, None));
+    for (index, category) in emoji_data::categories().iter().enumerate() {
+        // Every category uses its first emoji as its icon
+        let icon = category.emoji.first().map(|emoji| emoji.emoji).unwrap_or("?");
+        tabs = tabs.with_child(build_category_tab(icon, Some(index)));
+    }
+
+    widget::Flex::column()
+        .with_child(
+            widget::TextBox::new()
+                .with_placeholder("Search emoji")
+                .lens(AppState::emoji_picker.then(EmojiPickerState::search))
+                .expand_width()
+        )
+        .with_spacer(4.0)
+        .with_child(widget::Scroll::new(tabs).horizontal())
+        .with_spacer(4.0)
+        .with_flex_child(
+            widget::Scroll::new(EmojiGridWidget::new()).vertical().expand(),
+        1.0)
+        .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
+        .padding(6.0)
+        .background(crate::PANEL_BACKGROUND_KEY)
+        .border(druid::theme::BORDER_DARK, 1.0)
+        .rounded(6.0)
+}
+
+/// Picking a tab also clears the search, so the category shows
+fn build_category_tab(icon: &'static str, category: Option<usize>) -> impl Widget<AppState> {
+    widget::Label::new(icon)
+        .with_text_size(TAB_TEXT_SIZE)
+        .padding(3.0)
+        .background(widget::Painter::new(move |ctx, data: &AppState, _env| {
+            if data.emoji_picker.category == category && data.emoji_picker.search.is_empty() {
+                let rect = ctx.size().to_rect().to_rounded_rect(4.0);
+                ctx.fill(rect, &data.layout_settings.theme.hover_highlight_color.to_druid_color());
+            }
+        }))
+        .on_click(move |_ctx: &mut EventCtx, data: &mut AppState, _env: &_| {
+            data.emoji_picker.category = category;
+            data.emoji_picker.search = Default::default();
+        })
+}
+
+/// The emoji for the shortcode being typed, with the shortcode next to each
+pub(crate) fn shortcode_completions() -> CompletionList<ShortcodeCompletion,
+    impl Lens<AppState, im::Vector<ShortcodeCompletion>> + Copy, impl Lens<AppState, usize> + Copy>
+{
+    CompletionList {
+        completions: AppState::shortcode_completions,
+        selected: AppState::selected_completion,
+        row_text: |completion: &ShortcodeCompletion| format!("{}  :{}:", completion.emoji, completion.shortcode),
+        accept: AppState::accept_shortcode_completion,
+        update: AppState::update_shortcode_completions,
+    }
+}
@@ -0,0 +1,163 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use druid::piet::{ImageFormat, PietImage};
+use druid::{ImageBuf, PaintCtx, WindowId};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+/// How much memory the downscaled avatars can take up before the least
+/// recently used ones are dropped.
+const AVATAR_CACHE_MEMORY_CAP: usize = 32 * 1024 * 1024;
+const BYTES_PER_PIXEL: usize = 4;
+
+thread_local! {
+    // Only touched while painting, which is always on the UI thread
+    static AVATAR_CACHE: RefCell<AvatarCache> = RefCell::new(AvatarCache::new(AVATAR_CACHE_MEMORY_CAP));
+}
+
+/// Gets the avatar as a piet image scaled to `size` logical pixels, reusing
+/// the one from previous paints when possible.
+pub fn get_avatar_image(ctx: &mut PaintCtx, user_id: u32, source: &Arc<ImageBuf>, size: f64) -> PietImage {
+    let pixel_size = (size * ctx.scale().x()).round().max(1.0) as u32;
+    let key = AvatarCacheKey { window_id: ctx.window_id(), user_id, pixel_size };
+    AVATAR_CACHE.with(|cache| cache.borrow_mut().get_or_insert(ctx, key, source))
+}
+
+/// Drops the avatars made for a window. Called when the window closes, since its images can't be used by any other.
+pub fn remove_window(window_id: WindowId) {
+    AVATAR_CACHE.with(|cache| cache.borrow_mut().remove_window(window_id))
+}
+
+/// Piet images belong to the window's render context, so they aren't shared between windows.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct AvatarCacheKey {
+    window_id: WindowId,
+    user_id: u32,
+    pixel_size: u32,
+}
+
+struct AvatarCacheEntry {
+    /// The image this was made from, to notice when the user's avatar changes
+    source: Arc<ImageBuf>,
+    image: PietImage,
+    bytes: usize,
+    last_used: u64,
+}
+
+struct AvatarCache {
+    entries: HashMap<AvatarCacheKey, AvatarCacheEntry>,
+    total_bytes: usize,
+    memory_cap: usize,
+    /// Incremented on every lookup. Used to find the least recently used entry.
+    clock: u64,
+}
+
+impl AvatarCache {
+    fn new(memory_cap: usize) -> Self {
+        AvatarCache {
+            entries: HashMap::new(),
+            total_bytes: 0,
+            memory_cap,
+            clock: 0,
+        }
+    }
+
+    fn get_or_insert(&mut self, ctx: &mut PaintCtx, key: AvatarCacheKey, source: &Arc<ImageBuf>) -> PietImage {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            if Arc::ptr_eq(&entry.source, source) {
+                entry.last_used = self.clock;
+                return entry.image.clone();
+            }
+        }
+        self.remove(&key);
+
+        let scaled = downscale(source, key.pixel_size);
+        let image = scaled.to_image(ctx.render_ctx);
+        let bytes = scaled.width() * scaled.height() * BYTES_PER_PIXEL;
+        self.total_bytes += bytes;
+        self.entries.insert(key, AvatarCacheEntry {
+            source: source.clone(),
+            image: image.clone(),
+            bytes,
+            last_used: self.clock,
+        });
+        self.evict_to_cap(&key);
+        image
+    }
+
+    fn remove(&mut self, key: &AvatarCacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.bytes;
+        }
+    }
+
+    fn remove_window(&mut self, window_id: WindowId) {
+        let mut freed = 0;
+        self.entries.retain(|key, entry| {
+            if key.window_id == window_id {
+                freed += entry.bytes;
+                false
+            } else {
+                true
+            }
+        });
+        self.total_bytes -= freed;
+    }
+
+    /// Drops the least recently used entries until the cache fits, keeping the one just added.
+    fn evict_to_cap(&mut self, keep: &AvatarCacheKey) {
+        while self.total_bytes > self.memory_cap {
+            let oldest = self.entries.iter()
+                .filter(|(key, _)| *key != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Scales the image down so its longest side is `pixel_size`, using Lanczos
+/// filtering so small avatars stay sharp. Images that are already small
+/// enough are returned as they are.
+fn downscale(source: &ImageBuf, pixel_size: u32) -> ImageBuf {
+    let (width, height) = (source.width() as u32, source.height() as u32);
+    if width <= pixel_size && height <= pixel_size {
+        return source.clone();
+    }
+    let rgba = match to_rgba_image(source) {
+        Some(rgba) => rgba,
+        None => return source.clone(),
+    };
+    let scale = pixel_size as f64 / width.max(height) as f64;
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    let resized = imageops::resize(&rgba, new_width, new_height, FilterType::Lanczos3);
+    // Premultiplied stays premultiplied, everything else was expanded to separate alpha
+    let format = if source.format() == ImageFormat::RgbaPremul {
+        ImageFormat::RgbaPremul
+    } else {
+        ImageFormat::RgbaSeparate
+    };
+    ImageBuf::from_raw(resized.into_raw(), format, new_width as usize, new_height as usize)
+}
+
+fn to_rgba_image(source: &ImageBuf) -> Option<RgbaImage> {
+    let pixels = source.raw_pixels();
+    let rgba: Vec<u8> = match source.format() {
+        ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => pixels.to_vec(),
+        ImageFormat::Rgb => pixels.chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        ImageFormat::Grayscale => pixels.iter()
+            .flat_map(|gray| [*gray, *gray, *gray, 255])
+            .collect(),
+        _ => return None,
+    };
+    RgbaImage::from_raw(source.width() as u32, source.height() as u32, rgba)
+}
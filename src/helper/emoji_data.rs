@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Messages with up to this many emoji, and nothing else, are shown large
+pub const MAX_LARGE_EMOJI: usize = 3;
+/// Most shortcode completions shown at once
+pub const MAX_COMPLETIONS: usize = 6;
+/// Characters that have to follow the colon before completions show, so `:)` and times like `10:30` don't
+const MIN_SHORTCODE_CHARS: usize = 2;
+
+static CATEGORIES: OnceLock<Vec<EmojiCategory>> = OnceLock::new();
+/// The bundled emoji without variation selectors, and the most chars any of them has
+static KNOWN_EMOJI: OnceLock<HashSet<String>> = OnceLock::new();
+static LONGEST_KNOWN_EMOJI: OnceLock<usize> = OnceLock::new();
+
+pub struct Emoji {
+    pub emoji: &'static str,
+    /// Without the colons. The first is the main one.
+    pub shortcodes: Vec<&'static str>,
+}
+
+pub struct EmojiCategory {
+    pub name: &'static str,
+    pub emoji: Vec<Emoji>,
+}
+
+/// The bundled emoji, in the order the picker shows them
+pub fn categories() -> &'static [EmojiCategory] {
+    CATEGORIES.get_or_init(|| parse_emoji_list(include_str!("../assets/emoji.txt")))
+}
+
+fn parse_emoji_list(list: &'static str) -> Vec<EmojiCategory> {
+    let mut categories: Vec<EmojiCategory> = Vec::new();
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            categories.push(EmojiCategory { name, emoji: Vec::new() });
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        if let (Some(emoji), Some(category)) = (parts.next(), categories.last_mut()) {
+            category.emoji.push(Emoji { emoji, shortcodes: parts.collect() });
+        }
+    }
+    categories
+}
+
+fn all_emoji() -> impl Iterator<Item = &'static Emoji> {
+    categories().iter().flat_map(|category| category.emoji.iter())
+}
+
+/// Emoji with a shortcode containing the query. Ones where it's at the start come first.
+pub fn search(query: &str) -> Vec<&'static Emoji> {
+    let query = query.trim().to_lowercase();
+    let (mut starts_with, contains): (Vec<_>, Vec<_>) = all_emoji()
+        .filter(|emoji| emoji.shortcodes.iter().any(|shortcode| shortcode.contains(query.as_str())))
+        .partition(|emoji| emoji.shortcodes.iter().any(|shortcode| shortcode.starts_with(query.as_str())));
+    starts_with.extend(contains);
+    starts_with
+}
+
+/// The emoji and shortcode of every shortcode starting with the partial one, up to [MAX_COMPLETIONS]
+pub fn complete_shortcode(partial: &str) -> Vec<(&'static str, &'static str)> {
+    let partial = partial.to_lowercase();
+    all_emoji()
+        .flat_map(|emoji| emoji.shortcodes.iter().map(move |shortcode| (emoji.emoji, *shortcode)))
+        .filter(|(_, shortcode)| shortcode.starts_with(partial.as_str()))
+        .take(MAX_COMPLETIONS)
+        .collect()
+}
+
+/// The shortcode being typed at the end of the text, like `smi` for `hello :smi`.
+/// Returns the byte index of its colon and the part after it.
+pub fn shortcode_being_typed(text: &str) -> Option<(usize, &str)> {
+    let colon = text.rfind(':')?;
+    let partial = &text[colon + 1..];
+    let starts_word = text[..colon].chars().next_back().is_none_or(char::is_whitespace);
+    let is_shortcode = partial.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '+' || c == '-');
+    if starts_word && is_shortcode && partial.chars().count() >= MIN_SHORTCODE_CHARS {
+        Some((colon, partial))
+    } else {
+        None
+    }
+}
+
+/// How many emoji the text has, if it's only emoji and whitespace. Only the bundled emoji
+/// and flags count, so symbols like © or arrows that are usually shown as text don't.
+/// Skin tones, flags, and emoji joined with zero width joiners each count as one.
+pub fn count_if_only_emoji(text: &str) -> Option<usize> {
+    let mut count = 0;
+    for word in text.split_whitespace() {
+        // The bundled emoji are matched without them, so they can be typed either way
+        let chars: Vec<char> = word.chars().filter(|c| !is_variation_selector(*c)).collect();
+        let mut index = 0;
+        // After a zero width joiner, the next emoji is part of the same one
+        let mut is_joined = false;
+        while index < chars.len() {
+            index += emoji_len_at(&chars[index..])?;
+            while chars.get(index).is_some_and(|c| is_skin_tone(*c)) {
+                index += 1;
+            }
+            if !is_joined {
+                count += 1;
+            }
+            is_joined = chars.get(index) == Some(&ZERO_WIDTH_JOINER);
+            if is_joined {
+                index += 1;
+                if index == chars.len() {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(count)
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+fn known_emoji() -> &'static HashSet<String> {
+    KNOWN_EMOJI.get_or_init(|| all_emoji()
+        .map(|emoji| emoji.emoji.chars().filter(|c| !is_variation_selector(*c)).collect())
+        .collect())
+}
+
+/// The length in chars of the emoji at the start, if there is one. Longer emoji are tried first.
+fn emoji_len_at(chars: &[char]) -> Option<usize> {
+    if chars.len() >= 2 && chars[..2].iter().all(|c| is_regional_indicator(*c)) {
+        return Some(2);
+    }
+    let known_emoji = known_emoji();
+    let longest = *LONGEST_KNOWN_EMOJI.get_or_init(|| known_emoji.iter().map(|emoji| emoji.chars().count()).max().unwrap_or(0));
+    (1..=longest.min(chars.len())).rev()
+        .find(|len| known_emoji.contains(&chars[..*len].iter().collect::<String>()))
+}
+
+fn is_variation_selector(c: char) -> bool {
+    matches!(c, '\u{FE0E}' | '\u{FE0F}')
+}
+
+fn is_skin_tone(c: char) -> bool {
+    matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// Flags are a pair of these
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bundled_emoji() {
+        assert_eq!(count_if_only_emoji("😀"), Some(1));
+        assert_eq!(count_if_only_emoji(" 😀 😃😄 "), Some(3));
+        assert_eq!(count_if_only_emoji(""), Some(0));
+    }
+
+    #[test]
+    fn variation_selectors_are_optional() {
+        assert_eq!(count_if_only_emoji("❤️"), Some(1));
+        assert_eq!(count_if_only_emoji("❤"), Some(1));
+        assert_eq!(count_if_only_emoji("\u{FE0F}"), Some(0));
+    }
+
+    #[test]
+    fn symbols_usually_shown_as_text_are_not_emoji() {
+        assert_eq!(count_if_only_emoji("©"), None);
+        assert_eq!(count_if_only_emoji("™"), None);
+        assert_eq!(count_if_only_emoji("→"), None);
+        assert_eq!(count_if_only_emoji("⬛"), None);
+        assert_eq!(count_if_only_emoji("hi 😀"), None);
+    }
+
+    #[test]
+    fn skin_tones_flags_and_joined_emoji_count_once() {
+        assert_eq!(count_if_only_emoji("👍🏽"), Some(1));
+        assert_eq!(count_if_only_emoji("🇺🇸🇯🇵"), Some(2));
+        assert_eq!(count_if_only_emoji("❤\u{200D}🔥"), Some(1));
+        assert_eq!(count_if_only_emoji("😀\u{200D}"), None);
+        assert_eq!(count_if_only_emoji("🇺"), None);
+    }
+
+    #[test]
+    fn shortcodes() {
+        assert_eq!(shortcode_being_typed("hello :smi"), Some((6, "smi")));
+        assert_eq!(shortcode_being_typed("at 10:30"), None);
+        assert_eq!(shortcode_being_typed(":)"), None);
+        assert!(complete_shortcode("smi").contains(&("😄", "smile")));
+        assert_eq!(search("grinning").first().map(|emoji| emoji.emoji), Some("😀"));
+    }
+}
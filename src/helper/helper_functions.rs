@@ -1,5 +1,7 @@
-use druid::{BoxConstraints, Size};
+use druid::{BoxConstraints, Size, Widget};
+use druid::widget;
 use chrono::{ Datelike, TimeZone, Timelike};
+use crate::AppState;
 
 // A convenience function to convert the width to a BoxConstraints that has the input width
 /// as the max width, and the maximum height.
@@ -98,4 +100,30 @@ pub fn timestamp_to_display_msg(epoch: i64, format: TimestampFormat) -> String {
         chrono::LocalResult::Ambiguous(_a, _b) => { return "Amiguous".to_string(); },
         chrono::LocalResult::None => { return "Invalid Time".to_string(); },
     }
-}
\ No newline at end of file
+}
+
+/// A file size for display, like `512 B`, `12.3 KB` or `4.0 MB`
+pub fn format_file_size(size_in_bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if size_in_bytes < 1024 {
+        return format!("{} B", size_in_bytes);
+    }
+    let mut size = size_in_bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Wraps a window's root widget so that the standard druid widgets and the
+/// window chrome use the colors of the theme in the settings.
+pub fn with_theme<W: Widget<AppState>>(root: W) -> widget::EnvScope<AppState, W> {
+    widget::EnvScope::new(
+        |env: &mut druid::Env, data: &AppState| {
+            data.layout_settings.theme.set_env(env);
+        },
+        root
+    )
+}
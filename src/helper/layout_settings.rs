@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use druid;
 use druid::{BoxConstraints, Size, Point};
 use super::helper_functions::{self, TimestampFormat};
@@ -5,24 +6,178 @@ use crate::widgets::timeline_item_widget::{PictureShape, TailShape, ItemLayoutOp
 
 const DOT_SPACING: f64 = 5.0;
 
-const DARK_ON_DARK_COLOR: SimpleColor = SimpleColor { r: 175, g: 175, b: 175 };
-const DARK_ON_MEDIUM_COLOR: SimpleColor = SimpleColor { r: 200, g: 200, b: 200 };
-const DARK_ON_BLUE_COLOR: SimpleColor = SimpleColor { r: 210, g: 230, b: 255 };
-const WHITE_COLOR: SimpleColor = SimpleColor { r: 255, g: 255, b: 255 };
+const DARK_ON_DARK_COLOR: SimpleColor = SimpleColor::rgb(175, 175, 175);
+const DARK_ON_MEDIUM_COLOR: SimpleColor = SimpleColor::rgb(200, 200, 200);
+const DARK_ON_BLUE_COLOR: SimpleColor = SimpleColor::rgb(210, 230, 255);
+const WHITE_COLOR: SimpleColor = SimpleColor::rgb(255, 255, 255);
+const LIGHT_THEME_METADATA_COLOR: SimpleColor = SimpleColor::rgb(95, 95, 100);
 
-#[derive(Clone, druid::Data, druid::Lens)]
+#[derive(Clone, PartialEq, druid::Data, druid::Lens)]
 pub struct SimpleColor {
-    r: u8,
-    g: u8,
-    b: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity. 255 is fully opaque.
+    pub a: u8,
 }
 
 impl SimpleColor {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> SimpleColor {
+        SimpleColor { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> SimpleColor {
+        SimpleColor { r, g, b, a }
+    }
+
     pub fn to_druid_color(&self) -> druid::Color {
-        druid::Color::rgb8(self.r, self.g, self.b)
+        druid::Color::rgba8(self.r, self.g, self.b, self.a)
+    }
+
+    /// Creates a color from a hue in degrees, and saturation and value from 0.0 to 1.0
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64, a: u8) -> SimpleColor {
+        let chroma = value * saturation;
+        let hue_section = (hue.rem_euclid(360.0)) / 60.0;
+        let x = chroma * (1.0 - (hue_section % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue_section as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        let to_u8 = |channel: f64| ((channel + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        SimpleColor { r: to_u8(r), g: to_u8(g), b: to_u8(b), a }
+    }
+
+    /// Returns the hue in degrees, and the saturation and value from 0.0 to 1.0
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        (hue, saturation, max)
+    }
+
+    /// Formats as `#rrggbb`, or `#rrggbbaa` when not fully opaque
+    pub fn to_hex(&self) -> String {
+        if self.a == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// Parses `#rrggbb` or `#rrggbbaa`. The `#` is optional.
+    pub fn from_hex(hex: &str) -> Option<SimpleColor> {
+        let digits = hex.trim().trim_start_matches('#');
+        if (digits.len() != 6 && digits.len() != 8) || !digits.is_ascii() {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).ok();
+        let a = if digits.len() == 8 { channel(3)? } else { 255 };
+        Some(SimpleColor { r: channel(0)?, g: channel(1)?, b: channel(2)?, a })
+    }
+}
+
+/// The colors of the timeline and the window chrome around it.
+#[derive(Clone, druid::Data, druid::Lens)]
+pub struct ThemeSettings {
+    /// Bubble fill for messages from other users
+    pub other_bubble_color: SimpleColor,
+    /// Bubble fill for messages from the self user
+    pub self_bubble_color: SimpleColor,
+    /// Drawn over a message while the mouse is over it. Usually translucent.
+    pub hover_highlight_color: SimpleColor,
+    /// The line to the left of messages, when shown
+    pub left_line_color: SimpleColor,
+    /// Background of every window
+    pub window_background_color: SimpleColor,
+    /// Background of panels, such as the chat header and plugin rows
+    pub panel_background_color: SimpleColor,
+    /// Background of text boxes
+    pub input_background_color: SimpleColor,
+    /// Borders around panels and inputs
+    pub border_color: SimpleColor,
+    /// Used for focused inputs, switches, sliders, and other highlights
+    pub accent_color: SimpleColor,
+    /// Text outside of the message metadata
+    pub text_color: SimpleColor,
+}
+
+#[derive(Clone, Copy, PartialEq, druid::Data)]
+pub enum PredefinedTheme {
+    Dark,
+    Light,
+}
+
+impl ThemeSettings {
+    pub fn dark() -> ThemeSettings {
+        ThemeSettings {
+            other_bubble_color: SimpleColor::rgb(74, 74, 76),
+            self_bubble_color: SimpleColor::rgb(12, 131, 242),
+            hover_highlight_color: SimpleColor::rgba(255, 255, 255, 20),
+            left_line_color: SimpleColor::rgb(128, 128, 128),
+            window_background_color: SimpleColor::rgb(0, 0, 0),
+            panel_background_color: SimpleColor::rgb(40, 40, 40),
+            input_background_color: SimpleColor::rgb(58, 58, 58),
+            border_color: SimpleColor::rgb(128, 128, 128),
+            accent_color: SimpleColor::rgb(92, 196, 255),
+            text_color: SimpleColor::rgb(240, 240, 234),
+        }
+    }
+
+    pub fn light() -> ThemeSettings {
+        ThemeSettings {
+            other_bubble_color: SimpleColor::rgb(229, 229, 234),
+            self_bubble_color: SimpleColor::rgb(190, 222, 255),
+            hover_highlight_color: SimpleColor::rgba(0, 0, 0, 18),
+            left_line_color: SimpleColor::rgb(160, 160, 165),
+            window_background_color: SimpleColor::rgb(250, 250, 250),
+            panel_background_color: SimpleColor::rgb(236, 236, 240),
+            input_background_color: SimpleColor::rgb(255, 255, 255),
+            border_color: SimpleColor::rgb(190, 190, 195),
+            accent_color: SimpleColor::rgb(12, 131, 242),
+            text_color: SimpleColor::rgb(20, 20, 24),
+        }
+    }
+
+    /// Sets druid's own theme keys, and the panel key, so the standard widgets
+    /// and the window chrome follow the theme.
+    pub fn set_env(&self, env: &mut druid::Env) {
+        env.set(druid::theme::WINDOW_BACKGROUND_COLOR, self.window_background_color.to_druid_color());
+        env.set(druid::theme::BACKGROUND_DARK, self.window_background_color.to_druid_color());
+        env.set(druid::theme::BACKGROUND_LIGHT, self.input_background_color.to_druid_color());
+        env.set(druid::theme::TEXT_COLOR, self.text_color.to_druid_color());
+        env.set(druid::theme::BORDER_DARK, self.border_color.to_druid_color());
+        env.set(druid::theme::BORDER_LIGHT, self.border_color.to_druid_color());
+        env.set(druid::theme::PRIMARY_LIGHT, self.accent_color.to_druid_color());
+        env.set(druid::theme::PRIMARY_DARK, self.accent_color.to_druid_color());
+        env.set(druid::theme::CURSOR_COLOR, self.text_color.to_druid_color());
+        env.set(crate::PANEL_BACKGROUND_KEY, self.panel_background_color.to_druid_color());
     }
 }
 
+/// Data paired with the settings snapshot it should be laid out with.
+///
+/// The snapshot is shared by every widget in the timeline, and it is only
+/// replaced when a setting changes, so comparing it is a pointer comparison.
+pub type WithSettings<T> = (Arc<LayoutSettings>, T);
+
 #[derive(Clone, druid::Data, druid::Lens)]
 pub struct LayoutSettings {
     /// General layout
@@ -32,6 +187,14 @@ pub struct LayoutSettings {
     pub picture_shape: PictureShape,
     /// The height and width of the image
     pub picture_size: f64,
+    /// The corner radius for the rounded picture shapes
+    pub picture_corner_radius: f64,
+    /// Outline loaded from an SVG for the custom mask shape. Fitted to a 1x1 square.
+    pub picture_mask: Option<Arc<druid::kurbo::BezPath>>,
+    /// Whether to show the presence dot on the edge of the picture
+    pub show_presence: bool,
+    /// The diameter of the presence dot
+    pub presence_dot_size: f64,
     /// The tail shape, if a bubble
     pub chat_bubble_tail_shape: TailShape,
     /// how far the tail should go in the furthest direction
@@ -81,6 +244,8 @@ pub struct LayoutSettings {
     pub irc_stack_width: f64,
     /// How far should we push the text right to make it so they don't end up staggered.
     pub irc_header_width: f64,
+    /// Colors of the bubbles and the window chrome
+    pub theme: ThemeSettings,
 }
 
 
@@ -113,6 +278,10 @@ impl LayoutSettings {
             metadata_layout: MetadataLayout::LeftSideBySideWithDot,
             picture_shape: PictureShape::Circle,
             picture_size: 32.0,
+            picture_corner_radius: 4.0,
+            picture_mask: None,
+            show_presence: true,
+            presence_dot_size: 9.0,
             chat_bubble_tail_shape: TailShape::ConcaveBottom,
             chat_bubble_tail_size: 6.0,
             chat_bubble_radius: 4.0,
@@ -136,85 +305,21 @@ impl LayoutSettings {
             left_meta_offset: 2.0,
             irc_stack_width: 400.0,
             irc_header_width: 160.0,
-            sender_color: SimpleColor { r: 175, g: 175, b: 175 },
-            datetime_color: SimpleColor { r: 175, g: 175, b: 175 },
-            self_sender_color: SimpleColor { r: 175, g: 175, b: 175 },
-            self_datetime_color: SimpleColor { r: 175, g: 175, b: 175 },
+            sender_color: DARK_ON_DARK_COLOR,
+            datetime_color: DARK_ON_DARK_COLOR,
+            self_sender_color: DARK_ON_DARK_COLOR,
+            self_datetime_color: DARK_ON_DARK_COLOR,
+            theme: ThemeSettings::dark(),
         }
     }
 
-    pub fn from_env(env: &druid::Env) -> LayoutSettings{
-        let sender_color = env.get(crate::SENDER_COLOR_KEY).as_rgba8();
-        let datetime_color = env.get(crate::DATETIME_COLOR_KEY).as_rgba8();
-        let self_datetime_color = env.get(crate::SELF_DATETIME_COLOR_KEY).as_rgba8();
-        let self_sender_color = env.get(crate::SELF_SENDER_COLOR_KEY).as_rgba8();
-        LayoutSettings {
-            item_layout: num_traits::FromPrimitive::from_u64(env.get(crate::ITEM_LAYOUT_KEY)).expect("Invalid layout index"),
-            metadata_layout: num_traits::FromPrimitive::from_u64(env.get(crate::METADATA_LAYOUT_KEY)).expect("Invalid layout index"),
-            picture_shape: num_traits::FromPrimitive::from_u64(env.get(crate::PICTURE_SHAPE_KEY)).expect("Invalid picture shape index"),
-            picture_size: env.get(crate::PICTURE_SIZE_KEY),
-            chat_bubble_tail_shape: num_traits::FromPrimitive::from_u64(env.get(crate::CHAT_BUBBLE_TAIL_SHAPE_KEY)).expect("Invalid bubble tail shape index"),
-            chat_bubble_tail_size: env.get(crate::CHAT_BUBBLE_TAIL_SIZE_KEY),
-            chat_bubble_radius: env.get(crate::CHAT_BUBBLE_RADIUS_KEY),
-            chat_picture_spacing: env.get(crate::CHAT_BUBBLE_IMG_SPACING_KEY),
-            show_self_pic: env.get(crate::SHOW_SELF_PROFILE_PIC_KEY),
-            bubble_padding: env.get(crate::BUBBLE_PADDING_KEY),
-            metadata_content_spacing: env.get(crate::METADATA_CONTENT_SPACING_KEY),
-            align_to_picture: env.get(crate::ALIGN_TO_PICTURE),
-            group_spacing: env.get(crate::GROUP_SPACING_KEY),
-            single_message_spacing: env.get(crate::SINGLE_MESSAGE_SPACING_KEY),
-            show_left_line: env.get(crate::SHOW_LEFT_LINE_KEY),
-            left_spacing: env.get(crate::LEFT_SPACING_KEY),
-            left_bubble_flipped: env.get(crate::LEFT_BUBBLE_FLIPPED_KEY),
-            right_bubble_flipped: env.get(crate::RIGHT_BUBBLE_FLIPPED_KEY),
-            content_font_size: env.get(crate::CONTENT_FONT_SIZE_KEY),
-            sender_font_size: env.get(crate::SENDER_FONT_SIZE_KEY),
-            datetime_font_size: env.get(crate::DATETIME_FONT_SIZE_KEY),
-            metadata_font_bolded: env.get(crate::HEADER_FONT_BOLDED_KEY),
-            datetime_format: num_traits::FromPrimitive::from_u64(env.get(crate::DATETIME_FORMAT_KEY)).expect("Invalid datetime format index"),
-            side_time_format: num_traits::FromPrimitive::from_u64(env.get(crate::SIDE_TIME_FORMAT_KEY)).expect("Invalid side time format index"),
-            left_meta_offset: env.get(crate::LEFT_META_OFFSET_KEY),
-            irc_stack_width: env.get(crate::IRC_STACK_WIDTH_KEY),
-            irc_header_width: env.get(crate::IRC_HEADER_WIDTH_KEY),
-            sender_color: SimpleColor { r: sender_color.0, g: sender_color.1, b: sender_color.2 },
-            datetime_color: SimpleColor { r: datetime_color.0, g: datetime_color.1, b: datetime_color.2 },
-            self_datetime_color: SimpleColor { r: self_datetime_color.0, g: self_datetime_color.1, b: self_datetime_color.2 },
-            self_sender_color: SimpleColor { r: self_sender_color.0, g: self_sender_color.1, b: self_sender_color.2 },
-        }
-    }
-
-    pub fn set_env(&self, env: &mut druid::Env) {
-        env.set(crate::ITEM_LAYOUT_KEY, self.item_layout as u64);
-        env.set(crate::METADATA_LAYOUT_KEY, self.metadata_layout as u64);
-        env.set(crate::PICTURE_SHAPE_KEY, self.picture_shape as u64);
-        env.set(crate::PICTURE_SIZE_KEY, self.picture_size as f64);
-        env.set(crate::CHAT_BUBBLE_TAIL_SHAPE_KEY, self.chat_bubble_tail_shape as u64);
-        env.set(crate::CHAT_BUBBLE_TAIL_SIZE_KEY, self.chat_bubble_tail_size as f64);
-        env.set(crate::CHAT_BUBBLE_RADIUS_KEY, self.chat_bubble_radius as f64);
-        env.set(crate::CHAT_BUBBLE_IMG_SPACING_KEY, self.chat_picture_spacing as f64);
-        env.set(crate::SHOW_SELF_PROFILE_PIC_KEY, self.show_self_pic);
-        env.set(crate::BUBBLE_PADDING_KEY, self.bubble_padding as f64);
-        env.set(crate::METADATA_CONTENT_SPACING_KEY, self.metadata_content_spacing as f64);
-        env.set(crate::ALIGN_TO_PICTURE, self.align_to_picture as bool);
-        env.set(crate::GROUP_SPACING_KEY, self.group_spacing as f64);
-        env.set(crate::SINGLE_MESSAGE_SPACING_KEY, self.single_message_spacing as f64);
-        env.set(crate::SHOW_LEFT_LINE_KEY, self.show_left_line as bool);
-        env.set(crate::LEFT_SPACING_KEY, self.left_spacing as f64);
-        env.set(crate::LEFT_BUBBLE_FLIPPED_KEY, self.left_bubble_flipped as bool);
-        env.set(crate::RIGHT_BUBBLE_FLIPPED_KEY, self.right_bubble_flipped as bool);
-        env.set(crate::CONTENT_FONT_SIZE_KEY, self.content_font_size as f64);
-        env.set(crate::SENDER_FONT_SIZE_KEY, self.sender_font_size as f64);
-        env.set(crate::DATETIME_FONT_SIZE_KEY, self.datetime_font_size as f64);
-        env.set(crate::HEADER_FONT_BOLDED_KEY, self.metadata_font_bolded as bool);
-        env.set(crate::DATETIME_FORMAT_KEY, self.datetime_format as u64);
-        env.set(crate::SIDE_TIME_FORMAT_KEY, self.side_time_format as u64);
-        env.set(crate::LEFT_META_OFFSET_KEY, self.left_meta_offset);
-        env.set(crate::IRC_STACK_WIDTH_KEY, self.irc_stack_width);
-        env.set(crate::IRC_HEADER_WIDTH_KEY, self.irc_header_width);
-        env.set(crate::SENDER_COLOR_KEY, self.sender_color.to_druid_color());
-        env.set(crate::DATETIME_COLOR_KEY, self.datetime_color.to_druid_color());
-        env.set(crate::SELF_DATETIME_COLOR_KEY, self.self_datetime_color.to_druid_color());
-        env.set(crate::SELF_SENDER_COLOR_KEY, self.self_sender_color.to_druid_color());
+    /// Sets the env values that are still read through keys.
+    ///
+    /// Everything else is read from the shared settings snapshot passed down
+    /// with the data, but `List` takes its spacing as a key.
+    pub fn set_spacing_env(&self, env: &mut druid::Env) {
+        env.set(crate::GROUP_SPACING_KEY, self.group_spacing);
+        env.set(crate::SINGLE_MESSAGE_SPACING_KEY, self.single_message_spacing);
     }
 
     /// Gets the font for the title
@@ -553,6 +658,34 @@ impl LayoutSettings {
         }
     }
 
+    pub fn get_bubble_color(&self, is_self_user: bool) -> druid::Color {
+        if is_self_user {
+            self.theme.self_bubble_color.to_druid_color()
+        } else {
+            self.theme.other_bubble_color.to_druid_color()
+        }
+    }
+
+    /// Switches the theme colors, and the metadata colors so they stay readable on it.
+    pub fn set_from_predefined_theme(&mut self, theme: PredefinedTheme) {
+        match theme {
+            PredefinedTheme::Dark => {
+                self.theme = ThemeSettings::dark();
+                self.sender_color = DARK_ON_DARK_COLOR;
+                self.datetime_color = DARK_ON_DARK_COLOR;
+                self.self_sender_color = DARK_ON_DARK_COLOR;
+                self.self_datetime_color = DARK_ON_DARK_COLOR;
+            },
+            PredefinedTheme::Light => {
+                self.theme = ThemeSettings::light();
+                self.sender_color = LIGHT_THEME_METADATA_COLOR;
+                self.datetime_color = LIGHT_THEME_METADATA_COLOR;
+                self.self_sender_color = LIGHT_THEME_METADATA_COLOR;
+                self.self_datetime_color = LIGHT_THEME_METADATA_COLOR;
+            },
+        }
+    }
+
     pub fn set_from_predefined_layout(&mut self, layout: PredefinedLayout) {
         match layout {
             PredefinedLayout::ModernHangouts => {
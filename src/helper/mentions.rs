@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use druid::im;
+
+use crate::data::mention_data::Mention;
+
+/// Mentions travel in the message text as `<@id|name>`. Plugins translate them to and from
+/// their protocol's format, and the name keeps them readable without looking the user up.
+const TOKEN_START: &str = "<@";
+const TOKEN_END: char = '>';
+const NAME_SEPARATOR: char = '|';
+/// Typed in the composer to start a mention
+const MENTION_TRIGGER: char = '@';
+/// Most members shown in the mention list at once
+pub const MAX_MENTION_COMPLETIONS: usize = 6;
+
+pub fn make_token(user_id: u32, name: &str) -> String {
+    // These would end the token early
+    let name: String = name.chars().filter(|c| *c != NAME_SEPARATOR && *c != TOKEN_END).collect();
+    format!("{TOKEN_START}{user_id}{NAME_SEPARATOR}{name}{TOKEN_END}")
+}
+
+/// The user id and name of the token at the start of the text, and the token's length in bytes
+pub fn parse_token(text: &str) -> Option<(u32, &str, usize)> {
+    let rest = text.strip_prefix(TOKEN_START)?;
+    let end = rest.find(TOKEN_END)?;
+    let (user_id, name) = rest[..end].split_once(NAME_SEPARATOR)?;
+    let user_id = user_id.parse().ok()?;
+    Some((user_id, name, TOKEN_START.len() + end + TOKEN_END.len_utf8()))
+}
+
+/// Every token in the text, with its byte range
+fn find_tokens(text: &str) -> impl Iterator<Item = (Range<usize>, u32, &str)> {
+    text.match_indices(TOKEN_START).filter_map(move |(start, _)| {
+        let (user_id, name, len) = parse_token(&text[start..])?;
+        Some((start..start + len, user_id, name))
+    })
+}
+
+/// The ids in the text's tokens, in order. Users mentioned more than once are repeated.
+pub fn mentioned_users(text: &str) -> impl Iterator<Item = u32> + '_ {
+    find_tokens(text).map(|(_, user_id, _)| user_id)
+}
+
+pub fn mentions_user(text: &str, user_id: u32) -> bool {
+    find_tokens(text).any(|(_, mentioned, _)| mentioned == user_id)
+}
+
+/// The text with every token shown as `@name`, for copying and previews
+pub fn to_plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut position = 0;
+    for (range, _, name) in find_tokens(text) {
+        plain.push_str(&text[position..range.start]);
+        plain.push(MENTION_TRIGGER);
+        plain.push_str(name);
+        position = range.end;
+    }
+    plain.push_str(&text[position..]);
+    plain
+}
+
+/// Turns the `@name` of every mention picked in the composer into its token.
+/// Mentions whose name was deleted from the text since are left out. Names only match
+/// as whole words after an `@` that starts a word, so "Sam" doesn't match in `@Samantha`.
+pub fn encode_mentions(text: &str, mentions: &im::Vector<Mention>) -> String {
+    let mut mentions: Vec<&Mention> = mentions.iter().collect();
+    // Longer names first, so picking both "Sam" and "Sam Rivera" doesn't split the longer one
+    mentions.sort_by_key(|mention| std::cmp::Reverse(mention.name.len()));
+    let mut encoded = String::with_capacity(text.len());
+    let mut position = 0;
+    for (at, _) in text.match_indices(MENTION_TRIGGER) {
+        let starts_word = text[..at].chars().next_back().is_none_or(char::is_whitespace);
+        if at < position || !starts_word {
+            continue;
+        }
+        let after_trigger = &text[at + MENTION_TRIGGER.len_utf8()..];
+        let mention = mentions.iter().find(|mention| {
+            after_trigger.strip_prefix(mention.name.as_str())
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+        });
+        if let Some(mention) = mention {
+            encoded.push_str(&text[position..at]);
+            encoded.push_str(&make_token(mention.user_id, &mention.name));
+            position = at + MENTION_TRIGGER.len_utf8() + mention.name.len();
+        }
+    }
+    encoded.push_str(&text[position..]);
+    encoded
+}
+
+/// The name being typed after an `@` at the end of the text, like `sa` for `hi @sa`.
+/// Returns the byte index of the `@` and the part after it, which may be empty.
+pub fn mention_being_typed(text: &str) -> Option<(usize, &str)> {
+    let at = text.rfind(MENTION_TRIGGER)?;
+    let partial = &text[at + MENTION_TRIGGER.len_utf8()..];
+    // Not in the middle of an email address
+    let starts_word = text[..at].chars().next_back().is_none_or(char::is_whitespace);
+    let is_name = !partial.contains(char::is_whitespace);
+    if starts_word && is_name {
+        Some((at, partial))
+    } else {
+        None
+    }
+}
+
+/// Whether the name matches what was typed: any of its words starts with it, ignoring case
+pub fn name_matches(name: &str, partial: &str) -> bool {
+    let partial = partial.to_lowercase();
+    name.split_whitespace().any(|word| word.to_lowercase().starts_with(partial.as_str()))
+}
+
+/// What the composer shows for a picked mention, with a space to keep typing after it
+pub fn typed_mention(name: &str) -> String {
+    format!("{MENTION_TRIGGER}{name} ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(user_id: u32, name: &str) -> Mention {
+        Mention { user_id, name: name.to_string() }
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let token = make_token(3, "Sam|Rivera>");
+        assert_eq!(token, "<@3|SamRivera>");
+        assert_eq!(parse_token(&token), Some((3, "SamRivera", token.len())));
+        assert_eq!(parse_token("<@x|Sam>"), None);
+        assert!(mentions_user("hi <@3|Sam>", 3));
+        assert!(!mentions_user("hi @Sam", 3));
+        assert_eq!(to_plain_text("hi <@3|Sam> and <@4|Jo>"), "hi @Sam and @Jo");
+    }
+
+    #[test]
+    fn encoding_only_matches_whole_names() {
+        let mentions = im::vector![mention(1, "Sam")];
+        assert_eq!(encode_mentions("@Sam and @Samantha", &mentions), "<@1|Sam> and @Samantha");
+        assert_eq!(encode_mentions("sam@Sam.com", &mentions), "sam@Sam.com");
+        assert_eq!(encode_mentions("@Sam, hi", &mentions), "<@1|Sam>, hi");
+    }
+
+    #[test]
+    fn encoding_prefers_longer_names() {
+        let mentions = im::vector![mention(1, "Sam"), mention(2, "Sam Rivera")];
+        assert_eq!(encode_mentions("@Sam Rivera and @Sam", &mentions), "<@2|Sam Rivera> and <@1|Sam>");
+    }
+
+    #[test]
+    fn names_being_typed() {
+        assert_eq!(mention_being_typed("hi @sa"), Some((3, "sa")));
+        assert_eq!(mention_being_typed("hi @"), Some((3, "")));
+        assert_eq!(mention_being_typed("me@example"), None);
+        assert_eq!(mention_being_typed("@sam rivera"), None);
+        assert!(name_matches("Sam Rivera", "riv"));
+        assert!(!name_matches("Sam Rivera", "am"));
+        assert_eq!(typed_mention("Sam"), "@Sam ");
+    }
+}
@@ -0,0 +1,548 @@
+use std::ops::Range;
+
+use druid::im;
+use druid::piet::TextStorage as _;
+use druid::text::{AttributesAdder, RichText, RichTextBuilder};
+use druid::{FontFamily, FontStyle, FontWeight};
+
+use super::mentions;
+
+const CODE_FENCE: &str = "```";
+const QUOTE_PREFIX: char = '>';
+
+/// Schemes that are opened without asking first
+const SAFE_URL_SCHEMES: [&str; 2] = ["http", "https"];
+/// Trimmed off the end of URLs, since they're usually punctuation around the link
+const URL_TRAILING_PUNCTUATION: &[char] = &['.', ',', '!', '?', ';', ':', ')', '\'', '"'];
+/// The inline style markers, which can wrap a URL to style it
+const STYLE_MARKERS: &[char] = &['*', '_', '~'];
+
+/// A piece of a formatted message that's laid out on its own
+#[derive(Clone)]
+pub enum FormattedBlock {
+    Paragraph(FormattedText),
+    /// Shown in monospace with a background. Not parsed any further.
+    CodeBlock(String),
+    Quote(FormattedText),
+}
+
+#[derive(Clone)]
+pub struct FormattedText {
+    pub text: RichText,
+    pub links: Vec<LinkSpan>,
+}
+
+/// A URL found in the text
+#[derive(Clone)]
+pub struct LinkSpan {
+    /// Byte range in the formatted text, which has the formatting markers removed
+    pub range: Range<usize>,
+    pub url: String,
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    UnclosedCodeBlock,
+}
+
+/// Parses the message as a small, safe subset of Markdown: bold, italics,
+/// strikethrough, inline code, code blocks, and block quotes. Mention tokens of the users
+/// in `names` are shown as `@name` with their name from there. Other tokens are shown as
+/// plain text, so typing one can't pass for a mention. Falls back to the raw text if the
+/// message can't be parsed.
+pub fn format_message(text: &str, names: &im::HashMap<u32, String>) -> Vec<FormattedBlock> {
+    match parse_blocks(text, names) {
+        Ok(blocks) => blocks,
+        Err(_) => vec![FormattedBlock::Paragraph(FormattedText {
+            text: RichText::new(text.into()),
+            links: Vec::new(),
+        })],
+    }
+}
+
+/// The message as it's shown, without the formatting markers, for copying
+pub fn to_plain_text(text: &str, names: &im::HashMap<u32, String>) -> String {
+    let formatted = format_message(text, names);
+    let blocks: Vec<&str> = formatted.iter().map(|block| match block {
+        FormattedBlock::Paragraph(text) | FormattedBlock::Quote(text) => text.text.as_str(),
+        FormattedBlock::CodeBlock(code) => code.as_str(),
+    }).collect();
+    blocks.join("\n")
+}
+
+fn parse_blocks(text: &str, names: &im::HashMap<u32, String>) -> Result<Vec<FormattedBlock>, FormatError> {
+    let mut blocks = Vec::new();
+    let mut paragraph_lines: Vec<&str> = Vec::new();
+    let mut quote_lines: Vec<&str> = Vec::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        if is_opening_fence(line) {
+            flush_lines(&mut blocks, &mut paragraph_lines, FormattedBlock::Paragraph, names);
+            flush_lines(&mut blocks, &mut quote_lines, FormattedBlock::Quote, names);
+            let mut code_lines = Vec::new();
+            let mut closed = false;
+            for code_line in lines.by_ref() {
+                if code_line.trim() == CODE_FENCE {
+                    closed = true;
+                    break;
+                }
+                code_lines.push(code_line);
+            }
+            if !closed {
+                return Err(FormatError::UnclosedCodeBlock);
+            }
+            blocks.push(FormattedBlock::CodeBlock(code_lines.join("\n")));
+        } else if let Some(quoted) = line.trim_start().strip_prefix(QUOTE_PREFIX) {
+            flush_lines(&mut blocks, &mut paragraph_lines, FormattedBlock::Paragraph, names);
+            quote_lines.push(quoted.strip_prefix(' ').unwrap_or(quoted));
+        } else {
+            flush_lines(&mut blocks, &mut quote_lines, FormattedBlock::Quote, names);
+            paragraph_lines.push(line);
+        }
+    }
+    flush_lines(&mut blocks, &mut paragraph_lines, FormattedBlock::Paragraph, names);
+    flush_lines(&mut blocks, &mut quote_lines, FormattedBlock::Quote, names);
+    Ok(blocks)
+}
+
+/// A line that starts a code block has the fence and at most a language after it, like ` ```rust`.
+/// Lines with more text after the fence, like ` ```code``` here`, are parsed as inline code instead.
+fn is_opening_fence(line: &str) -> bool {
+    match line.trim().strip_prefix(CODE_FENCE) {
+        // The language isn't used
+        Some(language) => language.trim().chars().all(|c| c.is_alphanumeric() || "+-#._".contains(c)),
+        None => false,
+    }
+}
+
+/// Turns the collected lines into one block, if there are any
+fn flush_lines(
+    blocks: &mut Vec<FormattedBlock>,
+    lines: &mut Vec<&str>,
+    make_block: fn(FormattedText) -> FormattedBlock,
+    names: &im::HashMap<u32, String>,
+) {
+    if !lines.is_empty() {
+        blocks.push(make_block(build_text(&parse_inline(&lines.join("\n"), names))));
+        lines.clear();
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct InlineStyle {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SpanKind {
+    Text(InlineStyle),
+    Link(InlineStyle),
+    Code,
+    /// Mentions are bold in the link color, whatever the style around them
+    Mention,
+}
+
+/// A piece of the text with a single style, with the formatting markers removed
+#[derive(Clone, Debug, PartialEq)]
+struct InlineSpan {
+    text: String,
+    kind: SpanKind,
+}
+
+/// Collects the spans of a block of text
+#[derive(Default)]
+struct InlineBuilder {
+    spans: Vec<InlineSpan>,
+}
+
+impl InlineBuilder {
+    fn push_mention(&mut self, name: &str) {
+        self.push(format!("@{}", name), SpanKind::Mention);
+    }
+
+    fn push_code(&mut self, code: &str) {
+        self.push(code.to_string(), SpanKind::Code);
+    }
+
+    fn push_link(&mut self, url: &str, style: InlineStyle) {
+        self.push(url.to_string(), SpanKind::Link(style));
+    }
+
+    fn push_run(&mut self, run: &mut String, style: InlineStyle) {
+        self.push(std::mem::take(run), SpanKind::Text(style));
+    }
+
+    fn push(&mut self, text: String, kind: SpanKind) {
+        if !text.is_empty() {
+            self.spans.push(InlineSpan { text, kind });
+        }
+    }
+}
+
+/// Parses the inline styles. Markers without a matching closing marker are kept as text.
+/// URLs are found first, so markers in them, like the underscores in `https://host/_a_`, aren't parsed.
+fn parse_inline(text: &str, names: &im::HashMap<u32, String>) -> Vec<InlineSpan> {
+    let urls = find_urls(text);
+    let mut next_url = 0;
+    let mut builder = InlineBuilder::default();
+    let mut style = InlineStyle::default();
+    // Which of `*` and `_` started the italics, since only the same one ends them
+    let mut italic_marker: Option<char> = None;
+    let mut run = String::new();
+    let mut index = 0;
+
+    while index < text.len() {
+        // Inline code and mentions can skip past the start of a URL
+        while urls.get(next_url).is_some_and(|url| url.start < index) {
+            next_url += 1;
+        }
+        if let Some(url) = urls.get(next_url).filter(|url| url.start == index) {
+            builder.push_run(&mut run, style);
+            builder.push_link(&text[url.clone()], style);
+            index = url.end;
+            continue;
+        }
+        let rest = &text[index..];
+        if let Some((user_id, typed_name, token_len)) = mentions::parse_token(rest) {
+            match names.get(&user_id) {
+                Some(name) => {
+                    builder.push_run(&mut run, style);
+                    builder.push_mention(name);
+                },
+                None => run.push_str(&format!("@{}", typed_name)),
+            }
+            index += token_len;
+            continue;
+        }
+        if let Some(after_tick) = rest.strip_prefix('`') {
+            if let Some(code_len) = after_tick.find('`') {
+                builder.push_run(&mut run, style);
+                builder.push_code(&after_tick[..code_len]);
+                index += code_len + 2;
+                continue;
+            }
+        } else if let Some(after_marker) = rest.strip_prefix("**") {
+            if style.bold || after_marker.contains("**") {
+                builder.push_run(&mut run, style);
+                style.bold = !style.bold;
+                index += 2;
+                continue;
+            }
+        } else if let Some(after_marker) = rest.strip_prefix("~~") {
+            if style.strikethrough || after_marker.contains("~~") {
+                builder.push_run(&mut run, style);
+                style.strikethrough = !style.strikethrough;
+                index += 2;
+                continue;
+            }
+        } else if let Some(marker) = rest.chars().next().filter(|c| *c == '*' || *c == '_') {
+            let before = text[..index].chars().next_back();
+            let after = rest[1..].chars().next();
+            let toggles = match italic_marker {
+                Some(opened_with) => opened_with == marker && can_close_italic(before, after, marker),
+                None => can_open_italic(before, after, marker) && find_italic_closer(text, index + 1, marker, &urls).is_some(),
+            };
+            if toggles {
+                builder.push_run(&mut run, style);
+                italic_marker = if italic_marker.is_some() { None } else { Some(marker) };
+                style.italic = italic_marker.is_some();
+                index += 1;
+                continue;
+            }
+        }
+        let next_char = rest.chars().next().unwrap_or_default();
+        run.push(next_char);
+        index += next_char.len_utf8();
+    }
+    builder.push_run(&mut run, style);
+    builder.spans
+}
+
+/// Italics start before a word. Underscores inside words, like snake_case, aren't italics.
+fn can_open_italic(before: Option<char>, after: Option<char>, marker: char) -> bool {
+    let is_before_word = after.is_some_and(|c| !c.is_whitespace());
+    let is_inside_word = marker == '_' && before.is_some_and(char::is_alphanumeric);
+    is_before_word && !is_inside_word
+}
+
+/// Italics end after a word
+fn can_close_italic(before: Option<char>, after: Option<char>, marker: char) -> bool {
+    let is_after_word = before.is_some_and(|c| !c.is_whitespace());
+    let is_inside_word = marker == '_' && after.is_some_and(char::is_alphanumeric);
+    is_after_word && !is_inside_word
+}
+
+/// Finds where italics starting just before `start` would end. Skips over inline code,
+/// mentions, URLs, and `**`, so the first star of a later bold marker doesn't end them.
+fn find_italic_closer(text: &str, start: usize, marker: char, urls: &[Range<usize>]) -> Option<usize> {
+    let mut index = start;
+    while index < text.len() {
+        if let Some(url) = urls.iter().find(|url| url.contains(&index)) {
+            index = url.end;
+            continue;
+        }
+        let rest = &text[index..];
+        if let Some((_, _, token_len)) = mentions::parse_token(rest) {
+            index += token_len;
+            continue;
+        }
+        if let Some(after_tick) = rest.strip_prefix('`') {
+            if let Some(code_len) = after_tick.find('`') {
+                index += code_len + 2;
+                continue;
+            }
+        }
+        if marker == '*' && rest.starts_with("**") {
+            index += 2;
+            continue;
+        }
+        if rest.starts_with(marker) && can_close_italic(text[..index].chars().next_back(), rest[1..].chars().next(), marker) {
+            return Some(index);
+        }
+        index += rest.chars().next().unwrap_or_default().len_utf8();
+    }
+    None
+}
+
+/// Turns the spans into rich text, keeping track of where the links end up
+fn build_text(spans: &[InlineSpan]) -> FormattedText {
+    let mut builder = RichTextBuilder::new();
+    let mut links = Vec::new();
+    let mut len = 0;
+    for span in spans {
+        let mut attributes = builder.push(&span.text);
+        match span.kind {
+            SpanKind::Text(style) => apply_style(&mut attributes, style),
+            SpanKind::Link(style) => {
+                apply_style(&mut attributes, style);
+                attributes.underline(true).text_color(druid::theme::PRIMARY_LIGHT);
+                links.push(LinkSpan { range: len..len + span.text.len(), url: span.text.clone() });
+            },
+            SpanKind::Code => {
+                attributes.font_family(FontFamily::MONOSPACE);
+            },
+            SpanKind::Mention => {
+                attributes.weight(FontWeight::BOLD).text_color(druid::theme::PRIMARY_LIGHT);
+            },
+        }
+        len += span.text.len();
+    }
+    FormattedText { text: builder.build(), links }
+}
+
+fn apply_style(attributes: &mut AttributesAdder, style: InlineStyle) {
+    if style.bold {
+        attributes.weight(FontWeight::BOLD);
+    }
+    if style.italic {
+        attributes.style(FontStyle::Italic);
+    }
+    if style.strikethrough {
+        attributes.strikethrough(true);
+    }
+}
+
+/// Finds things like `https://example.com` or `mailto:someone@example.com`.
+/// Style markers around a whole URL, like `**https://example.com**`, aren't part of it.
+fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut word_start = None;
+    // A trailing space makes sure the last word is checked too
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_whitespace(), word_start) {
+            (false, None) => word_start = Some(index),
+            (true, Some(start)) => {
+                let word = &text[start..index];
+                let markers_len = word.len() - word.trim_start_matches(STYLE_MARKERS).len();
+                let markers = &word[..markers_len];
+                let url = word[markers_len..]
+                    .trim_end_matches(URL_TRAILING_PUNCTUATION)
+                    .trim_end_matches(|c| markers.contains(c))
+                    .trim_end_matches(URL_TRAILING_PUNCTUATION);
+                if is_url(url) {
+                    urls.push(start + markers_len..start + markers_len + url.len());
+                }
+                word_start = None;
+            },
+            _ => {},
+        }
+    }
+    urls
+}
+
+fn is_url(word: &str) -> bool {
+    let (scheme, rest) = match word.split_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let is_valid_scheme = scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '.' || c == '-');
+    let has_target = rest.strip_prefix("//").map_or(
+        scheme.eq_ignore_ascii_case("mailto") && rest.contains('@'),
+        |after_slashes| !after_slashes.is_empty()
+    );
+    is_valid_scheme && has_target
+}
+
+/// Whether the link can be opened without asking the user first
+pub fn is_safe_url(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        SAFE_URL_SCHEMES.iter().any(|safe| scheme.eq_ignore_ascii_case(safe))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, kind: SpanKind) -> InlineSpan {
+        InlineSpan { text: text.to_string(), kind }
+    }
+
+    fn names() -> im::HashMap<u32, String> {
+        let mut names = im::HashMap::new();
+        names.insert(3, "Sam Rivera".to_string());
+        names
+    }
+
+    fn parse(text: &str) -> Vec<InlineSpan> {
+        parse_inline(text, &names())
+    }
+
+    fn format(text: &str) -> Vec<FormattedBlock> {
+        format_message(text, &names())
+    }
+
+    fn plain_text(text: &str) -> String {
+        to_plain_text(text, &names())
+    }
+
+    fn plain(text: &str) -> InlineSpan {
+        span(text, SpanKind::Text(InlineStyle::default()))
+    }
+
+    const BOLD: InlineStyle = InlineStyle { bold: true, italic: false, strikethrough: false };
+    const ITALIC: InlineStyle = InlineStyle { bold: false, italic: true, strikethrough: false };
+    const BOLD_ITALIC: InlineStyle = InlineStyle { bold: true, italic: true, strikethrough: false };
+
+    fn block_text(block: &FormattedBlock) -> &str {
+        match block {
+            FormattedBlock::Paragraph(text) | FormattedBlock::Quote(text) => text.text.as_str(),
+            FormattedBlock::CodeBlock(code) => code,
+        }
+    }
+
+    #[test]
+    fn bold_and_strikethrough() {
+        assert_eq!(parse("a **b** c"), vec![plain("a "), span("b", SpanKind::Text(BOLD)), plain(" c")]);
+        let struck = InlineStyle { strikethrough: true, ..InlineStyle::default() };
+        assert_eq!(parse("~~gone~~"), vec![span("gone", SpanKind::Text(struck))]);
+        assert_eq!(parse("**open"), vec![plain("**open")]);
+    }
+
+    #[test]
+    fn italics() {
+        assert_eq!(parse("*a* _b_"), vec![
+            span("a", SpanKind::Text(ITALIC)), plain(" "), span("b", SpanKind::Text(ITALIC)),
+        ]);
+        assert_eq!(parse("snake_case_name"), vec![plain("snake_case_name")]);
+        assert_eq!(parse("2 * 3 * 4"), vec![plain("2 * 3 * 4")]);
+    }
+
+    #[test]
+    fn italics_ignore_the_stars_of_bold() {
+        assert_eq!(parse("*not italic **bold**"), vec![plain("*not italic "), span("bold", SpanKind::Text(BOLD))]);
+        assert_eq!(parse("*a **b** c*"), vec![
+            span("a ", SpanKind::Text(ITALIC)),
+            span("b", SpanKind::Text(BOLD_ITALIC)),
+            span(" c", SpanKind::Text(ITALIC)),
+        ]);
+        assert_eq!(parse("*a _b* c_"), vec![span("a _b", SpanKind::Text(ITALIC)), plain(" c_")]);
+    }
+
+    #[test]
+    fn inline_code_is_not_parsed() {
+        assert_eq!(parse("run `cargo *test*` now"), vec![plain("run "), span("cargo *test*", SpanKind::Code), plain(" now")]);
+        assert_eq!(parse("*a `b*` c"), vec![plain("*a "), span("b*", SpanKind::Code), plain(" c")]);
+    }
+
+    #[test]
+    fn code_blocks_need_a_fence_on_its_own_line() {
+        let blocks = format("before\n```rust\nlet x = *y*;\n```\nafter");
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(blocks[1], FormattedBlock::CodeBlock(_)));
+        assert_eq!(block_text(&blocks[1]), "let x = *y*;");
+
+        let blocks = format("```not a fence``` here");
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(blocks[0], FormattedBlock::Paragraph(_)));
+        assert_eq!(parse("```not a fence``` here"), vec![span("not a fence", SpanKind::Code), plain(" here")]);
+    }
+
+    #[test]
+    fn unclosed_code_blocks_show_the_raw_text() {
+        let blocks = format("```\n**code**");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(block_text(&blocks[0]), "```\n**code**");
+    }
+
+    #[test]
+    fn quotes() {
+        let blocks = format("> quoted\n>more\nreply");
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(blocks[0], FormattedBlock::Quote(_)));
+        assert_eq!(block_text(&blocks[0]), "quoted\nmore");
+        assert!(matches!(blocks[1], FormattedBlock::Paragraph(_)));
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(parse("see https://example.com."), vec![
+            plain("see "), span("https://example.com", SpanKind::Link(InlineStyle::default())), plain("."),
+        ]);
+        assert_eq!(find_urls("mailto:someone@example.com not:a url"), vec![0..26]);
+        assert!(is_safe_url("HTTPS://example.com"));
+        assert!(!is_safe_url("file:///etc/passwd"));
+    }
+
+    #[test]
+    fn markers_inside_urls_are_not_parsed() {
+        let no_style = InlineStyle::default();
+        assert_eq!(parse("https://host/_a_"), vec![span("https://host/_a_", SpanKind::Link(no_style))]);
+        assert_eq!(parse("_see https://host/a_b_ too_"), vec![
+            span("see ", SpanKind::Text(ITALIC)),
+            span("https://host/a_b_", SpanKind::Link(ITALIC)),
+            span(" too", SpanKind::Text(ITALIC)),
+        ]);
+        assert_eq!(parse("**https://example.com**!"), vec![
+            span("https://example.com", SpanKind::Link(BOLD)), plain("!"),
+        ]);
+    }
+
+    #[test]
+    fn links_point_at_the_formatted_text() {
+        let formatted = build_text(&parse("**hi** https://example.com"));
+        assert_eq!(formatted.text.as_str(), "hi https://example.com");
+        assert_eq!(formatted.links.len(), 1);
+        assert_eq!(formatted.links[0].range, 3..22);
+    }
+
+    #[test]
+    fn plain_text_has_no_markers_or_tokens() {
+        assert_eq!(plain_text("**hi** <@3|Sam>\n> `quoted`\n```\nlet x = 1;\n```"), "hi @Sam Rivera\nquoted\nlet x = 1;");
+    }
+
+    #[test]
+    fn mentions_show_the_members_name() {
+        assert_eq!(parse("hi <@3|Sam_R>_"), vec![plain("hi "), span("@Sam Rivera", SpanKind::Mention), plain("_")]);
+    }
+
+    #[test]
+    fn mentions_of_unknown_users_are_plain_text() {
+        assert_eq!(parse("hi <@9|Admin>"), vec![plain("hi @Admin")]);
+        assert_eq!(parse("<@x|Admin>"), vec![plain("<@x|Admin>")]);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod avatar_cache;
+pub mod emoji_data;
 pub mod helper_functions;
-pub mod layout_settings;
\ No newline at end of file
+pub mod layout_settings;
+pub mod mentions;
+pub mod message_formatting;
+pub mod shape_paths;
\ No newline at end of file
@@ -0,0 +1,272 @@
+use druid::kurbo::{Affine, BezPath, Circle, Line, ParamCurve, Point, Rect, RoundedRect, Shape, Vec2};
+use usvg::NodeExt;
+
+use crate::helper::layout_settings::LayoutSettings;
+use crate::widgets::timeline_item_widget::PictureShape;
+
+/// Accuracy used when turning curved shapes into paths
+const PATH_TOLERANCE: f64 = 0.1;
+/// Higher is closer to a square
+const SQUIRCLE_EXPONENT: f64 = 4.0;
+const SQUIRCLE_SEGMENTS: usize = 64;
+/// How much of the picture's height is cut off the top and bottom of the hexagon
+const HEXAGON_VERTICAL_TRIM: f64 = 0.08;
+/// How far in the top and bottom corners of the hexagon are
+const HEXAGON_INSET: f64 = 0.25;
+/// How far along each edge the octagon's corners are cut
+const OCTAGON_CORNER_FRACTION: f64 = 0.25;
+
+/// Builds the outline of the profile picture in the given rect.
+/// Every shape is built at the origin and then moved to the rect, so they all
+/// follow the picture wherever it is drawn.
+pub fn make_picture_path(settings: &LayoutSettings, pic_rect: Rect) -> BezPath {
+    let size = pic_rect.width().min(pic_rect.height());
+    let radius = settings.picture_corner_radius;
+    let mut path = match settings.picture_shape {
+        PictureShape::Rectangle => Rect::new(0.0, 0.0, size, size).to_path(PATH_TOLERANCE),
+        PictureShape::RoundedRectangle => RoundedRect::new(0.0, 0.0, size, size, radius).to_path(PATH_TOLERANCE),
+        PictureShape::Circle => make_circle_path(size),
+        PictureShape::Hexagon => make_rounded_polygon_path(&hexagon_points(size), 0.0),
+        PictureShape::RoundedHexagon => make_rounded_polygon_path(&hexagon_points(size), radius),
+        PictureShape::Octagon => make_rounded_polygon_path(&octagon_points(size), 0.0),
+        PictureShape::Squircle => make_squircle_path(size),
+        PictureShape::CustomMask => match &settings.picture_mask {
+            Some(mask) => {
+                let mut mask = (**mask).clone();
+                mask.apply_affine(Affine::scale(size));
+                mask
+            },
+            // Nothing loaded yet
+            None => make_circle_path(size),
+        },
+    };
+    path.apply_affine(Affine::translate(pic_rect.origin().to_vec2()));
+    path
+}
+
+fn make_circle_path(size: f64) -> BezPath {
+    Circle::new(Point::new(size / 2.0, size / 2.0), size / 2.0).to_path(PATH_TOLERANCE)
+}
+
+fn hexagon_points(size: f64) -> Vec<Point> {
+    let second_x = size * HEXAGON_INSET;
+    let third_x = size * (1.0 - HEXAGON_INSET);
+    let top_y = size * HEXAGON_VERTICAL_TRIM;
+    let middle_y = size / 2.0;
+    let bottom_y = size * (1.0 - HEXAGON_VERTICAL_TRIM);
+    vec![
+        Point::new(0.0, middle_y),
+        Point::new(second_x, top_y),
+        Point::new(third_x, top_y),
+        Point::new(size, middle_y),
+        Point::new(third_x, bottom_y),
+        Point::new(second_x, bottom_y),
+    ]
+}
+
+fn octagon_points(size: f64) -> Vec<Point> {
+    let dist_from_corner = size * OCTAGON_CORNER_FRACTION;
+    let other_side_pos = size - dist_from_corner;
+    vec![
+        Point::new(0.0, dist_from_corner),
+        Point::new(dist_from_corner, 0.0),
+        Point::new(other_side_pos, 0.0),
+        Point::new(size, dist_from_corner),
+        Point::new(size, other_side_pos),
+        Point::new(other_side_pos, size),
+        Point::new(dist_from_corner, size),
+        Point::new(0.0, other_side_pos),
+    ]
+}
+
+/// A closed polygon through the points, with every corner rounded by the radius.
+/// The radius is limited to half of the shortest edge so corners don't overlap.
+fn make_rounded_polygon_path(points: &[Point], radius: f64) -> BezPath {
+    let mut path = BezPath::new();
+    let point_count = points.len();
+    for index in 0..point_count {
+        let corner = points[index];
+        let previous = points[(index + point_count - 1) % point_count];
+        let next = points[(index + 1) % point_count];
+        let to_previous = previous - corner;
+        let to_next = next - corner;
+        let corner_radius = radius.max(0.0)
+            .min(to_previous.hypot() / 2.0)
+            .min(to_next.hypot() / 2.0);
+        let curve_start = corner + unit_or_zero(to_previous) * corner_radius;
+        let curve_end = corner + unit_or_zero(to_next) * corner_radius;
+        if index == 0 {
+            path.move_to(curve_start);
+        } else {
+            path.line_to(curve_start);
+        }
+        if corner_radius > 0.0 {
+            path.quad_to(corner, curve_end);
+        }
+    }
+    path.close_path();
+    path
+}
+
+fn unit_or_zero(vector: Vec2) -> Vec2 {
+    let length = vector.hypot();
+    if length > 0.0 { vector / length } else { Vec2::ZERO }
+}
+
+/// A superellipse that fills the square
+fn make_squircle_path(size: f64) -> BezPath {
+    let half_size = size / 2.0;
+    let mut path = BezPath::new();
+    for step in 0..SQUIRCLE_SEGMENTS {
+        let angle = step as f64 / SQUIRCLE_SEGMENTS as f64 * std::f64::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        let x = cos.signum() * cos.abs().powf(2.0 / SQUIRCLE_EXPONENT);
+        let y = sin.signum() * sin.abs().powf(2.0 / SQUIRCLE_EXPONENT);
+        let point = Point::new(half_size + x * half_size, half_size + y * half_size);
+        if step == 0 {
+            path.move_to(point);
+        } else {
+            path.line_to(point);
+        }
+    }
+    path.close_path();
+    path
+}
+
+/// Reads every path in the SVG into one mask, scaled and centered to fit
+/// a 1x1 square so it can be scaled to any picture size.
+pub fn load_svg_mask(svg_data: &[u8]) -> Result<BezPath, String> {
+    let options = usvg::Options::default();
+    let tree = usvg::Tree::from_data(svg_data, &options.to_ref()).map_err(|err| err.to_string())?;
+
+    let mut mask = BezPath::new();
+    for node in tree.root().descendants() {
+        if let usvg::NodeKind::Path(ref svg_path) = *node.borrow() {
+            let transform = node.abs_transform();
+            let mut path = BezPath::new();
+            for segment in svg_path.data.iter() {
+                match *segment {
+                    usvg::PathSegment::MoveTo { x, y } => path.move_to((x, y)),
+                    usvg::PathSegment::LineTo { x, y } => path.line_to((x, y)),
+                    usvg::PathSegment::CurveTo { x1, y1, x2, y2, x, y } => path.curve_to((x1, y1), (x2, y2), (x, y)),
+                    usvg::PathSegment::ClosePath => path.close_path(),
+                }
+            }
+            path.apply_affine(Affine::new([transform.a, transform.b, transform.c, transform.d, transform.e, transform.f]));
+            mask.extend(path.elements().iter().copied());
+        }
+    }
+    if mask.elements().is_empty() {
+        return Err("The SVG has no paths to use as a mask".to_string());
+    }
+
+    let view_box = tree.svg_node().view_box.rect;
+    let longest_side = view_box.width().max(view_box.height());
+    if longest_side <= 0.0 {
+        return Err("The SVG has an empty view box".to_string());
+    }
+    let centering = Vec2::new(
+        (longest_side - view_box.width()) / 2.0 - view_box.x(),
+        (longest_side - view_box.height()) / 2.0 - view_box.y(),
+    );
+    mask.apply_affine(Affine::scale(1.0 / longest_side) * Affine::translate(centering));
+    Ok(mask)
+}
+
+/// Where the outline crosses the line from the picture's center to its
+/// bottom right corner. Used to put the presence dot on the edge of any shape.
+pub fn get_outline_point(picture_path: &BezPath, pic_rect: Rect) -> Point {
+    let center = pic_rect.center();
+    let corner = Point::new(pic_rect.x1, pic_rect.y1);
+    let ray = Line::new(center, corner);
+    picture_path.segments()
+        .flat_map(|segment| segment.intersect_line(ray))
+        .map(|intersection| ray.eval(intersection.line_t))
+        // The outermost crossing, in case the shape has holes or several parts
+        .max_by(|a, b| a.distance(center).total_cmp(&b.distance(center)))
+        .unwrap_or(corner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rect_close(actual: Rect, expected: Rect) {
+        let close = (actual.x0 - expected.x0).abs() < 1e-6 && (actual.y0 - expected.y0).abs() < 1e-6
+            && (actual.x1 - expected.x1).abs() < 1e-6 && (actual.y1 - expected.y1).abs() < 1e-6;
+        assert!(close, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn polygon_points_fill_the_square() {
+        let hexagon = hexagon_points(10.0);
+        assert_eq!(hexagon.len(), 6);
+        let octagon = octagon_points(10.0);
+        assert_eq!(octagon.len(), 8);
+        for point in hexagon.iter().chain(octagon.iter()) {
+            assert!((0.0..=10.0).contains(&point.x) && (0.0..=10.0).contains(&point.y), "{:?} is outside", point);
+        }
+        assert_rect_close(make_rounded_polygon_path(&octagon, 0.0).bounding_box(), Rect::new(0.0, 0.0, 10.0, 10.0));
+    }
+
+    #[test]
+    fn sharp_polygons_are_only_lines() {
+        let path = make_rounded_polygon_path(&hexagon_points(10.0), 0.0);
+        // One move, a line to each other corner, and the close
+        assert_eq!(path.elements().len(), 7);
+    }
+
+    #[test]
+    fn corner_radius_is_limited_by_the_edges() {
+        let square = [Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 10.0), Point::new(0.0, 10.0)];
+        let path = make_rounded_polygon_path(&square, 100.0);
+        // Each corner curve starts and ends at the middle of its edges
+        assert_eq!(path.elements()[0], druid::kurbo::PathEl::MoveTo(Point::new(0.0, 5.0)));
+        let bounds = path.bounding_box();
+        assert!(bounds.x0 >= 0.0 && bounds.y0 >= 0.0 && bounds.x1 <= 10.0 && bounds.y1 <= 10.0, "{:?}", bounds);
+    }
+
+    #[test]
+    fn unit_or_zero_handles_zero_length() {
+        assert_eq!(unit_or_zero(Vec2::new(3.0, 4.0)), Vec2::new(0.6, 0.8));
+        assert_eq!(unit_or_zero(Vec2::ZERO), Vec2::ZERO);
+    }
+
+    #[test]
+    fn squircle_fills_the_square() {
+        assert_rect_close(make_squircle_path(20.0).bounding_box(), Rect::new(0.0, 0.0, 20.0, 20.0));
+    }
+
+    #[test]
+    fn picture_path_is_moved_to_the_picture() {
+        let mut settings = LayoutSettings::default();
+        settings.picture_shape = PictureShape::Rectangle;
+        let path = make_picture_path(&settings, Rect::new(5.0, 10.0, 25.0, 40.0));
+        // Uses the shorter side so the picture stays square
+        assert_rect_close(path.bounding_box(), Rect::new(5.0, 10.0, 25.0, 30.0));
+    }
+
+    #[test]
+    fn svg_mask_is_centered_in_a_unit_square() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 20 10"><path d="M0 0 H20 V10 H0 Z"/></svg>"#;
+        let mask = load_svg_mask(svg).unwrap();
+        assert_rect_close(mask.bounding_box(), Rect::new(0.0, 0.25, 1.0, 0.75));
+    }
+
+    #[test]
+    fn svg_without_paths_is_not_a_mask() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"></svg>"#;
+        assert!(load_svg_mask(svg).is_err());
+    }
+
+    #[test]
+    fn outline_point_is_on_the_shape_edge() {
+        let pic_rect = Rect::new(0.0, 0.0, 20.0, 20.0);
+        let square = Rect::new(0.0, 0.0, 20.0, 20.0).to_path(PATH_TOLERANCE);
+        assert!(get_outline_point(&square, pic_rect).distance(Point::new(20.0, 20.0)) < 1e-6);
+
+        let circle = make_circle_path(20.0);
+        let distance = get_outline_point(&circle, pic_rect).distance(pic_rect.center());
+        assert!((distance - 10.0).abs() < PATH_TOLERANCE, "{} from the center", distance);
+    }
+}
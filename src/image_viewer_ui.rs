@@ -0,0 +1,23 @@
+use druid::{EventCtx, WindowDesc};
+use crate::data::attachment_data::Attachment;
+use crate::helper::helper_functions;
+use crate::widgets::image_viewer_widget::ImageViewerWidget;
+
+/// The viewer opens at the image's size, but no bigger than this
+const MAX_VIEWER_SIZE: (f64, f64) = (1200.0, 900.0);
+const MIN_VIEWER_SIZE: (f64, f64) = (200.0, 150.0);
+
+/// Opens an image attachment in its own window, where it can be zoomed and panned
+pub(crate) fn open_image_viewer(ctx: &mut EventCtx, attachment: &Attachment) {
+    let image = match &attachment.image {
+        Some(image) => image.clone(),
+        None => return,
+    };
+    let window_size = (
+        (image.width() as f64).clamp(MIN_VIEWER_SIZE.0, MAX_VIEWER_SIZE.0),
+        (image.height() as f64).clamp(MIN_VIEWER_SIZE.1, MAX_VIEWER_SIZE.1),
+    );
+    ctx.new_window(WindowDesc::new(helper_functions::with_theme(ImageViewerWidget::new(image)))
+        .title(attachment.file_name.clone())
+        .window_size(window_size));
+}
@@ -0,0 +1,61 @@
+use druid::{Application, EventCtx, Menu, MenuItem, Point, Widget, WidgetExt, WindowDesc};
+use druid::widget;
+use log::error;
+use crate::AppState;
+use crate::helper::helper_functions;
+use crate::helper::message_formatting;
+
+/// Opens http(s) links right away. Other schemes can start any program that's
+/// registered for them, so the user is asked first.
+pub(crate) fn open_link(ctx: &mut EventCtx, url: &str) {
+    if message_formatting::is_safe_url(url) {
+        open_in_system(url);
+    } else {
+        ctx.new_window(WindowDesc::new(build_link_confirmation_ui(url.to_string()))
+            .title("Open Link?")
+            .window_size((360.0, 140.0))
+            .resizable(false));
+    }
+}
+
+pub(crate) fn show_link_menu(ctx: &mut EventCtx, url: &str, position: Point) {
+    let url = url.to_string();
+    let menu = Menu::new("Link")
+        .entry(MenuItem::new("Copy Link").on_activate(move |_ctx, _data: &mut AppState, _env| {
+            Application::global().clipboard().put_string(&url);
+        }));
+    ctx.show_context_menu(menu, position);
+}
+
+fn open_in_system(url: &str) {
+    let result = opener::open(url);
+    if result.is_err() {
+        error!("Failed to open link {}: {}", url, result.unwrap_err())
+    }
+}
+
+fn build_link_confirmation_ui(url: String) -> impl Widget<AppState> {
+    let url_to_open = url.clone();
+    helper_functions::with_theme(
+        widget::Flex::column()
+            .with_child(widget::Label::new("This link will be opened by another program:"))
+            .with_default_spacer()
+            .with_child(widget::Label::new(url).with_line_break_mode(widget::LineBreaking::WordWrap))
+            .with_flex_spacer(1.0)
+            .with_child(
+                widget::Flex::row()
+                    .with_child(widget::Button::new("Cancel").on_click(|ctx: &mut EventCtx, _data: &mut AppState, _env| {
+                        ctx.window().close();
+                    }))
+                    .with_default_spacer()
+                    .with_child(widget::Button::new("Open").on_click(move |ctx: &mut EventCtx, _data: &mut AppState, _env| {
+                        open_in_system(&url_to_open);
+                        ctx.window().close();
+                    }))
+                .main_axis_alignment(widget::MainAxisAlignment::End)
+            )
+        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        .padding(10.0)
+        .background(druid::theme::WINDOW_BACKGROUND_COLOR)
+    )
+}
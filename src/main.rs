@@ -1,14 +1,14 @@
-use druid::{AppLauncher, Color, WindowDesc, PlatformError, AppDelegate, WidgetExt};
+use druid::{AppLauncher, WindowDesc, PlatformError, AppDelegate, WidgetExt};
 use druid::widget::{Widget, Button, Flex, Label};
 use druid::widget;
 use druid::widget::prelude::*;
 use druid::im;
 use druid;
 
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
-use log::warn;
+use log::{debug, warn};
 
 use tokio;
 
@@ -16,6 +16,7 @@ use tokio;
 use polychat_ipc;
 
 use helper::layout_settings::LayoutSettings;
+use helper::helper_functions;
 
 use data::app_state_data::*;
 
@@ -23,47 +24,30 @@ mod widgets;
 mod helper;
 mod data;
 mod settings_ui;
+mod attachment_ui;
 mod chat_ui;
+mod completion_ui;
+mod conversation_ui;
+mod emoji_ui;
+mod image_viewer_ui;
+mod link_ui;
+mod mention_ui;
+mod message_ui;
 mod plugin_ui;
+mod typing_ui;
 mod core_interface;
 
-// Env keys to define layout in the environment
-pub const ITEM_LAYOUT_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.item_layout");
-pub const METADATA_LAYOUT_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.metadata_layout");
-pub const PICTURE_SHAPE_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.picture_shape");
-pub const PICTURE_SIZE_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.picture_size");
-pub const CHAT_BUBBLE_TAIL_SHAPE_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.tail_shape");
-pub const CHAT_BUBBLE_TAIL_SIZE_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.tail_size");
-pub const CHAT_BUBBLE_RADIUS_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.bubble_radius");
-pub const CHAT_BUBBLE_IMG_SPACING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.bubble_img_spacing");
-pub const SHOW_SELF_PROFILE_PIC_KEY: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.show_self_pic");
-pub const BUBBLE_PADDING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.bubble_padding");
-pub const METADATA_CONTENT_SPACING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.metadata_content_padding");
-pub const ALIGN_TO_PICTURE: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.align-to-picture");
+// Env keys for the settings that widgets read as keys. Everything else comes
+// from the LayoutSettings snapshot passed down with the timeline data.
 pub const GROUP_SPACING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.group_spacing");
 pub const SINGLE_MESSAGE_SPACING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.single_message_spacing");
-pub const SHOW_LEFT_LINE_KEY: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.show_left_line");
-pub const LEFT_SPACING_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.left_spacing");
-pub const LEFT_BUBBLE_FLIPPED_KEY: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.left_bubble_flipped");
-pub const RIGHT_BUBBLE_FLIPPED_KEY: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.right_bubble_flipped");
-pub const SENDER_FONT_SIZE_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.sender_font_size");
-pub const CONTENT_FONT_SIZE_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.content_font_size");
-pub const DATETIME_FONT_SIZE_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.datetime_font_size");
-pub const HEADER_FONT_BOLDED_KEY: druid::env::Key<bool> = druid::env::Key::new("polysoft.druid-demo.metadata_font_bolded");
-pub const DATETIME_FORMAT_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.datetime_format");
-pub const SIDE_TIME_FORMAT_KEY: druid::env::Key<u64> = druid::env::Key::new("polysoft.druid-demo.side_time_format");
-pub const SENDER_COLOR_KEY: druid::env::Key<druid::Color> = druid::env::Key::new("polysoft.druid-demo.sender_color");
-pub const DATETIME_COLOR_KEY: druid::env::Key<druid::Color> = druid::env::Key::new("polysoft.druid-demo.datetime_color");
-pub const SELF_DATETIME_COLOR_KEY: druid::env::Key<druid::Color> = druid::env::Key::new("polysoft.druid-demo.self_datetime_color");
-pub const SELF_SENDER_COLOR_KEY: druid::env::Key<druid::Color> = druid::env::Key::new("polysoft.druid-demo.self_sender_color");
-pub const LEFT_META_OFFSET_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.left_meta_offset");
-pub const IRC_STACK_WIDTH_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.irc_stack_width");
-pub const IRC_HEADER_WIDTH_KEY: druid::env::Key<f64> = druid::env::Key::new("polysoft.druid-demo.irc_header_width");
-// Commands to communicate things that need to happen
-const REFRESH_UI_SELECTOR: druid::Selector = druid::Selector::new("polysoft.druid-demo.refresh_ui");
+// Set from the theme, for chrome that isn't covered by druid's own theme keys
+pub const PANEL_BACKGROUND_KEY: druid::env::Key<druid::Color> = druid::env::Key::new("polysoft.druid-demo.panel_background");
 
 struct Delegate {
     window_count: i32,
+    /// Passes requests from the widgets to the core thread
+    core_requests: mpsc::Sender<core_interface::CoreRequest>,
 }
 
 impl AppDelegate<AppState> for Delegate {
@@ -82,10 +66,21 @@ impl AppDelegate<AppState> for Delegate {
         &mut self,
         _ctx: &mut druid::DelegateCtx,
         _target: druid::Target,
-        _cmd: &druid::Command,
+        cmd: &druid::Command,
         _data: &mut AppState,
         _env: &druid::Env,
     ) -> druid::Handled {
+        if let Some(request) = cmd.get(core_interface::SEND_TO_CORE) {
+            if !core_interface::CORE_SUPPORT.supports(request) {
+                debug!("The core doesn't support {:?} yet. Dropping it.", request);
+                return druid::Handled::Yes;
+            }
+            let result = self.core_requests.send(request.clone());
+            if result.is_err() {
+                warn!("Failed to send request to the core. Err: {:?}", result.err())
+            }
+            return druid::Handled::Yes;
+        }
         druid::Handled::No
     }
 
@@ -100,8 +95,9 @@ impl AppDelegate<AppState> for Delegate {
         self.window_count += 1;
     }
 
-    fn window_removed(&mut self, _id: druid::WindowId, data: &mut AppState, _env: &druid::Env, _ctx: &mut druid::DelegateCtx) {
+    fn window_removed(&mut self, id: druid::WindowId, data: &mut AppState, _env: &druid::Env, _ctx: &mut druid::DelegateCtx) {
         self.window_count -= 1;
+        helper::avatar_cache::remove_window(id);
         data.settings_open = false;
         if self.window_count <= 0 {
             println!("All windows closed. Quitting...");
@@ -126,26 +122,27 @@ fn get_main_window_row() -> impl Widget<AppState> {
         .with_flex_child(
             Flex::column()
                 .with_child(Label::new("Conversations").padding(5.0))
+                .with_child(conversation_ui::build_conversation_ui())
                 .with_child(Button::new("Open Chat Window").on_click( |ctx: &mut EventCtx, _data: &mut AppState, _ | {
-                    ctx.new_window(WindowDesc::new(chat_ui::build_chat_ui())
-                        .window_size((300.0, 450.0)));
+                    chat_ui::open_chat_window(ctx);
                 }))
                 .expand_width()
         , 1.0)
     .cross_axis_alignment(widget::CrossAxisAlignment::Start)
     .expand_height()
-    .background(Color::BLACK)
-    
 }
 
 fn get_main_window_widget(tx: mpsc::Sender<bool>) -> impl Widget<AppState> {
-    Flex::column()
-        .with_child(Label::new("PolyChat").padding(5.0))
-        .with_flex_child(get_main_window_row(), 1.0)
-    .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
-    .on_added( move |&mut _, _ctx: &mut LifeCycleCtx, _data: &AppState, _env: &Env| {
-        notify_gui_ready(tx.clone());
-    })
+    helper_functions::with_theme(
+        Flex::column()
+            .with_child(Label::new("PolyChat").padding(5.0))
+            .with_flex_child(get_main_window_row(), 1.0)
+        .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
+        .background(druid::theme::WINDOW_BACKGROUND_COLOR)
+        .on_added( move |&mut _, _ctx: &mut LifeCycleCtx, _data: &AppState, _env: &Env| {
+            notify_gui_ready(tx.clone());
+        })
+    )
 }
 
 
@@ -169,9 +166,22 @@ fn main() -> Result<(), PlatformError> {
     let initial_state = AppState {
         text_edit: "".to_string().into(),
         timeline_data: im::vector![],
-        profile_pics: im::vector![],
+        avatars: im::HashMap::new(),
+        presences: im::HashMap::new(),
+        display_names: im::HashMap::new(),
+        conversations: im::vector![],
+        replying_to: None,
+        pending_attachments: im::vector![],
+        emoji_picker: Default::default(),
+        shortcode_completions: im::vector![],
+        selected_completion: 0,
+        mention_completions: im::vector![],
+        selected_mention: 0,
+        composer_mentions: im::vector![],
+        conversation_members: im::vector![],
+        typing_users: im::vector![],
         settings_open: false,
-        layout_settings: LayoutSettings::default(),
+        layout_settings: Arc::new(LayoutSettings::default()),
         plugin_load_status: "Not loaded.".to_string(),
         plugin_load_dir: None,
         plugin_list: im::vector![],
@@ -179,11 +189,13 @@ fn main() -> Result<(), PlatformError> {
 
     println!("Starting the GUI.");
     let (tx, rx) = mpsc::channel(); // A channel to send info from the UI to the core.
+    let (request_tx, request_rx) = mpsc::channel(); // A channel for the user's actions, like deleting a message.
     let launcher = AppLauncher::with_window(
         get_main_window_desc(tx)
     ).delegate(
         Delegate {
             window_count: 0,
+            core_requests: request_tx,
         }
     );
     let event_sink = launcher.get_external_handle();
@@ -208,6 +220,7 @@ fn main() -> Result<(), PlatformError> {
             println!("Initializing core from new thread in GUI");
             let application_core = polychat_ipc::core::Core::new_in_home();
             let core_interface = core_interface::CoreInterface::new(event_sink);
+            thread::spawn(move || core_interface::handle_core_requests(request_rx));
 
             println!("Starting core in another thread from the GUI.");
             application_core.unwrap().run(&core_interface).unwrap();
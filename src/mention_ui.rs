@@ -0,0 +1,17 @@
+use druid::{im, Lens};
+use crate::AppState;
+use crate::completion_ui::CompletionList;
+use crate::data::mention_data::Mention;
+
+/// The members matching the name typed after an `@`
+pub(crate) fn mention_completions() -> CompletionList<Mention,
+    impl Lens<AppState, im::Vector<Mention>> + Copy, impl Lens<AppState, usize> + Copy>
+{
+    CompletionList {
+        completions: AppState::mention_completions,
+        selected: AppState::selected_mention,
+        row_text: |mention: &Mention| format!("@{}", mention.name),
+        accept: AppState::accept_mention_completion,
+        update: AppState::update_mention_completions,
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use druid::{Application, EventCtx, Menu, MenuItem, Point, Selector};
+use crate::{AppState, Message};
+use crate::core_interface::{CoreRequest, CORE_SUPPORT, SEND_TO_CORE};
+use crate::helper::helper_functions;
+use crate::helper::message_formatting;
+
+// Sent by the message menu with the message's id. The chat window starts a reply or an edit,
+// or opens the emoji picker to react with.
+pub(crate) const REPLY_TO_MESSAGE: Selector<u32> = Selector::new("polysoft.druid-demo.reply_to_message");
+pub(crate) const EDIT_MESSAGE: Selector<u32> = Selector::new("polysoft.druid-demo.edit_message");
+pub(crate) const ADD_REACTION: Selector<u32> = Selector::new("polysoft.druid-demo.add_reaction");
+/// Sent by the message menu with the message's id. The chat window passes it on to the core.
+pub(crate) const DELETE_MESSAGE: Selector<u32> = Selector::new("polysoft.druid-demo.delete_message");
+/// Sent by reply previews with the original message's id. Its widget scrolls into view.
+pub(crate) const SCROLL_TO_MESSAGE: Selector<u32> = Selector::new("polysoft.druid-demo.scroll_to_message");
+/// How long our edit can stay pending before we give up on the core confirming it
+pub(crate) const PENDING_EDIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sent by the inline editor with the message's id and its new text
+pub(crate) const SAVE_MESSAGE_EDIT: Selector<(u32, String)> = Selector::new("polysoft.druid-demo.save_message_edit");
+/// Sent by the reaction chips with the message's id and the chip's emoji, and by the emoji picker with the one picked
+pub(crate) const TOGGLE_REACTION: Selector<(u32, String)> = Selector::new("polysoft.druid-demo.toggle_reaction");
+
+/// The menu shown when right-clicking a message, or clicking its menu button.
+/// Copy uses the selected text when there is some, and the whole message as it's shown otherwise.
+/// Actions the core can't carry out yet are left out.
+pub(crate) fn show_message_menu(ctx: &mut EventCtx, message: &Message, selected_text: Option<String>, position: Point) {
+    if message.deleted {
+        // Nothing left to act on
+        return;
+    }
+    let message_id = message.id;
+    let text_to_copy = selected_text.unwrap_or_else(|| message_formatting::to_plain_text(message.displayed_text(), &message.mention_names));
+    let message_to_copy = message.clone();
+    let mut menu = Menu::new("Message")
+        .entry(MenuItem::new("Reply").on_activate(move |ctx, _data: &mut AppState, _env| {
+            ctx.submit_command(REPLY_TO_MESSAGE.with(message_id));
+        }));
+    if CORE_SUPPORT.reactions {
+        menu = menu.entry(MenuItem::new("Add Reaction").on_activate(move |ctx, _data: &mut AppState, _env| {
+            ctx.submit_command(ADD_REACTION.with(message_id));
+        }));
+    }
+    menu = menu
+        .separator()
+        .entry(MenuItem::new("Copy").on_activate(move |_ctx, _data: &mut AppState, _env| {
+            Application::global().clipboard().put_string(&text_to_copy);
+        }))
+        .entry(MenuItem::new("Copy Message").on_activate(move |_ctx, data: &mut AppState, _env| {
+            Application::global().clipboard().put_string(format_message_for_copy(data, &message_to_copy));
+        }));
+    if message.is_own() && (CORE_SUPPORT.edit_messages || CORE_SUPPORT.delete_messages) {
+        menu = menu.separator();
+        if CORE_SUPPORT.edit_messages {
+            menu = menu.entry(MenuItem::new("Edit").on_activate(move |ctx, _data: &mut AppState, _env| {
+                ctx.submit_command(EDIT_MESSAGE.with(message_id));
+            }));
+        }
+        if CORE_SUPPORT.delete_messages {
+            menu = menu.entry(MenuItem::new("Delete").on_activate(move |ctx, _data: &mut AppState, _env| {
+                ctx.submit_command(DELETE_MESSAGE.with(message_id));
+            }));
+        }
+    }
+    ctx.show_context_menu(menu, position);
+}
+
+/// Turns a message action from the menu into a request for the core
+fn get_core_request(cmd: &druid::Command) -> Option<CoreRequest> {
+    if let Some((message_id, new_text)) = cmd.get(SAVE_MESSAGE_EDIT) {
+        Some(CoreRequest::EditMessage { message_id: *message_id, new_text: new_text.clone() })
+    } else if let Some(message_id) = cmd.get(DELETE_MESSAGE) {
+        Some(CoreRequest::DeleteMessage { message_id: *message_id })
+    } else if let Some((message_id, emoji)) = cmd.get(TOGGLE_REACTION) {
+        Some(CoreRequest::ToggleReaction { message_id: *message_id, emoji: emoji.clone() })
+    } else {
+        None
+    }
+}
+
+/// Forwards the message actions to the core. Returns whether the command was one of them.
+pub(crate) fn forward_message_command(ctx: &mut EventCtx, cmd: &druid::Command) -> bool {
+    match get_core_request(cmd) {
+        Some(request) => {
+            ctx.submit_command(SEND_TO_CORE.with(request));
+            ctx.set_handled();
+            true
+        },
+        None => false,
+    }
+}
+
+/// The message as it's shown with the sender and timestamp, like `[timestamp] Sender: message`
+fn format_message_for_copy(data: &AppState, message: &Message) -> String {
+    format!(
+        "[{}] {}: {}",
+        helper_functions::timestamp_to_display_msg(message.timestamp_epoch_seconds, data.layout_settings.datetime_format),
+        data.get_display_name(message.user_id),
+        message_formatting::to_plain_text(message.displayed_text(), &message.mention_names)
+    )
+}
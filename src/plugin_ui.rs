@@ -1,4 +1,4 @@
-use druid::{Widget, WidgetExt, Color};
+use druid::{Widget, WidgetExt};
 use druid::widget;
 use druid::widget::prelude::*;
 use log::{error, warn};
@@ -29,8 +29,8 @@ pub(crate) fn build_plugin_ui() -> impl Widget<AppState> {
                         .center()
                 , 1.0)
                 .with_child(widget::Button::new("Add Account"))
-            .background(Color::rgb8(40, 40, 40))
-            .border(Color::GRAY, 1.0)
+            .background(crate::PANEL_BACKGROUND_KEY)
+            .border(druid::theme::BORDER_DARK, 1.0)
             .padding(1.0)
         }).lens(AppState::plugin_list))
         .with_child(
@@ -1,18 +1,30 @@
 use crate::helper::helper_functions::TimestampFormat;
-use crate::widgets::timeline_item_widget::{PictureShape, TailShape, ItemLayoutOption, MetadataLayout};
-use druid::{EventCtx, Widget, WidgetExt};
-use druid::widget;
-use crate::helper::layout_settings::{LayoutSettings, PredefinedLayout};
-use crate::AppState;
+use crate::widgets::timeline_item_widget::{PictureShape, TailShape, ItemLayoutOption, MetadataLayout, TimelineItemWidget};
+use std::sync::Arc;
+use druid::{Data, Env, Event, EventCtx, FileDialogOptions, FileInfo, FileSpec, Selector, Widget, WidgetExt, Lens, LensExt, im};
+use druid::{widget, lens};
+use tracing::error;
+use crate::helper::layout_settings::{LayoutSettings, PredefinedLayout, PredefinedTheme, SimpleColor, ThemeSettings, WithSettings};
+use crate::helper::{helper_functions, shape_paths};
+use crate::widgets::color_picker_widget::{self, RecentColors};
+use crate::data::sample_data;
+use crate::{AppState, MessageGroup};
 
+const PREVIEW_WIDTH: f64 = 380.0;
+/// Sent by the open dialog when an SVG is chosen for the picture mask
+const PICTURE_MASK_SELECTED: Selector<FileInfo> = Selector::new("polysoft.druid-demo.picture_mask_selected");
 
-const IMG_SHAPE_OPTIONS: [(&str, PictureShape); 5] =
+
+const IMG_SHAPE_OPTIONS: [(&str, PictureShape); 8] =
 [
     ("Circle", PictureShape::Circle),
     ("Rectangle", PictureShape::Rectangle),
     ("Rounded Rectangle", PictureShape::RoundedRectangle),
+    ("Squircle", PictureShape::Squircle),
     ("Hexagon", PictureShape::Hexagon),
+    ("Rounded Hexagon", PictureShape::RoundedHexagon),
     ("Octagon", PictureShape::Octagon),
+    ("Custom SVG Mask", PictureShape::CustomMask),
 ];
 const TAIL_SHAPE_OPTIONS: [(&str, TailShape); 6] =
 [
@@ -53,9 +65,64 @@ const TIME_OPTIONS: [(&str, TimestampFormat); 3] =
 ];
 
 pub(crate) fn build_settings_ui() -> impl Widget<AppState> {
-    widget::Tabs::new()
-        .with_tab("Layouts", build_predefined_styles_settings().lens(AppState::layout_settings))
-        .with_tab("Advanced", build_advanced_settings().lens(AppState::layout_settings))
+    // Shared by every color picker in the window
+    let recent_colors = RecentColors::default();
+    helper_functions::with_theme(
+        widget::Flex::row()
+            .with_flex_child(
+                widget::Tabs::new()
+                    .with_tab("Layouts", build_predefined_styles_settings().lens(layout_settings_lens()))
+                    .with_tab("Advanced", build_advanced_settings(&recent_colors).lens(layout_settings_lens()))
+                    .with_tab("Theme", build_theme_settings(&recent_colors).lens(layout_settings_lens()))
+            , 1.0)
+            .with_child(build_settings_preview().lens(AppState::layout_settings))
+            .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+            .background(druid::theme::WINDOW_BACKGROUND_COLOR)
+    )
+}
+
+/// A timeline of sample messages that follows the settings as they change.
+fn build_settings_preview() -> impl Widget<Arc<LayoutSettings>> {
+    let conversation = sample_data::preview_conversation();
+    let timeline = widget::Scroll::new(
+        widget::List::new(|| {
+            TimelineItemWidget::new()
+        })
+        .with_spacing(crate::GROUP_SPACING_KEY)
+        .padding(5.0)
+    )
+    .vertical()
+    // The sample conversation is read only, so edits are dropped
+    .lens(lens::Map::new(
+        move |settings: &Arc<LayoutSettings>| (settings.clone(), conversation.clone()),
+        |_: &mut Arc<LayoutSettings>, _: WithSettings<im::Vector<MessageGroup>>| {},
+    ))
+    .expand_height()
+    .background(crate::PANEL_BACKGROUND_KEY);
+
+    widget::Flex::column()
+        .with_child(
+            widget::Label::new("Preview")
+                .with_text_size(20.0).padding(8.0).align_left()
+        )
+        .with_flex_child(
+            widget::EnvScope::new(
+                |env: &mut druid::Env, data: &Arc<LayoutSettings>| {
+                    data.set_spacing_env(env);
+                },
+                timeline
+            )
+        , 1.0)
+        .fix_width(PREVIEW_WIDTH)
+        .padding(8.0)
+}
+
+/// Lens into the settings behind the shared snapshot.
+///
+/// The snapshot is copied on write, and only when a value actually changed,
+/// so the timeline only sees a new snapshot when there is something to relayout.
+fn layout_settings_lens() -> impl Lens<AppState, LayoutSettings> {
+    AppState::layout_settings.then(lens::InArc::new::<LayoutSettings, LayoutSettings>(lens::Identity))
 }
 
 fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
@@ -75,68 +142,68 @@ fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
                     widget::Flex::column()
                         .with_child(
                             widget::Button::new("Modern Hangouts")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::ModernHangouts, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::ModernHangouts);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Modern Bubble")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::ModernBubble, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::ModernBubble);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Large Bubble")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::LargeBubble, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::LargeBubble);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Old Fashioned Hangouts")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::OldHangouts, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::OldHangouts);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Telegram")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Telegram, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Telegram);
                                 })
                         )
                         .with_child(
                             widget::Button::new("iMessage")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::IMessage, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::IMessage);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Old Kik")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::OldKik, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::OldKik);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Tear Drop")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::TearDrop, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::TearDrop);
                                 })
                         )
                         .with_child(
                             widget::Button::new("No Tail")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Tailless, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Tailless);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Relaxed")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Relaxed, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Relaxed);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Other Bubble")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::OtherBubble, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::OtherBubble);
                                 })
                         )
                         .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
@@ -145,44 +212,44 @@ fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
                     widget::Flex::column()
                         .with_child(
                             widget::Button::new("Discord")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Discord, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Discord);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Compact Discord")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::CompactDiscord, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::CompactDiscord);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Slack")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Slack, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Slack);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Compact")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::Compact, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::Compact);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Modern IRC")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::IRC, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::IRC);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Large IRC")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::LargeIRC, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::LargeIRC);
                                 })
                         )
                         .with_child(
                             widget::Button::new("Spaced Modern IRC")
-                                .on_click( |ctx: &mut EventCtx, data: &mut LayoutSettings, _ | {
-                                    predefined_layout_selected(ctx, PredefinedLayout::SpacedIRC, data);
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_layout(PredefinedLayout::SpacedIRC);
                                 })
                         )
                         .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
@@ -195,9 +262,6 @@ fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
                         .with_default_spacer()
                         .with_flex_child(
                             widget::Stepper::new()
-                            .on_click( |ctx: &mut EventCtx, _, _ | {
-                                ui_changed_callback(ctx);
-                            })
                             .lens(LayoutSettings::content_font_size)
                         , 0.9)
                         .with_flex_child(
@@ -213,9 +277,6 @@ fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
                             .with_default_spacer()
                             .with_flex_child(
                                 widget::Stepper::new()
-                                .on_click( |ctx: &mut EventCtx, _, _ | {
-                                    ui_changed_callback(ctx);
-                                })
                                 .lens(LayoutSettings::sender_font_size)
                             , 0.9)
                             .with_flex_child(
@@ -231,9 +292,6 @@ fn build_predefined_styles_settings() -> impl Widget<LayoutSettings> {
                             .with_default_spacer()
                             .with_flex_child(
                                 widget::Stepper::new()
-                                .on_click( |ctx: &mut EventCtx, _, _ | {
-                                    ui_changed_callback(ctx);
-                                })
                                 .lens(LayoutSettings::datetime_font_size)
                             , 0.9)
                             .with_flex_child(
@@ -259,9 +317,6 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::RadioGroup::column(LAYOUT_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
                         .lens(LayoutSettings::item_layout)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -275,9 +330,6 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::RadioGroup::column(METADATA_LAYOUT_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
                         .lens(LayoutSettings::metadata_layout)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -290,11 +342,23 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 , 0.7)
                 .with_default_spacer()
                 .with_flex_child(
-                    widget::RadioGroup::column(IMG_SHAPE_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
-                        .lens(LayoutSettings::picture_shape)
+                    widget::Flex::column()
+                        .with_child(
+                            widget::RadioGroup::column(IMG_SHAPE_OPTIONS)
+                                .lens(LayoutSettings::picture_shape)
+                        )
+                        .with_child(
+                            widget::Button::new("Load SVG Mask...")
+                                .on_click( |ctx: &mut EventCtx, _, _ | {
+                                    let svg = FileSpec::new("SVG", &["svg"]);
+                                    ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(
+                                        FileDialogOptions::new()
+                                            .allowed_types(vec![svg])
+                                            .accept_command(PICTURE_MASK_SELECTED)
+                                    ));
+                                })
+                        )
+                        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
         )
@@ -306,9 +370,6 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(10.0, 100.0).with_step(1.0)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::picture_size)
                 , 1.0)
                 .with_flex_child(widget::Label::new(
@@ -317,6 +378,50 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
         )
         .with_spacer(10.0)
+        .with_child(
+            widget::Flex::row()
+                .with_flex_child(widget::Label::new("Profile Pic Corner Radius:").align_right()
+                , 0.7)
+                .with_default_spacer()
+                .with_flex_child(
+                    widget::Slider::new().with_range(0.0, 20.0).with_step(0.5)
+                    .lens(LayoutSettings::picture_corner_radius)
+                , 1.0)
+                .with_flex_child(widget::Label::new(
+                    |data: &LayoutSettings, _: &_| {format!("{:.1}", data.picture_corner_radius)}),
+                    0.3)
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        )
+        .with_spacer(10.0)
+        .with_child(widget::Flex::row()
+            .with_flex_child(widget::Label::new("Show Presence:")
+                .with_line_break_mode(widget::LineBreaking::WordWrap)
+                .align_right()
+            , 0.7)
+            .with_default_spacer()
+            .with_flex_child(
+                widget::Switch::new()
+                .lens(LayoutSettings::show_presence)
+            , 1.3)
+            .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        )
+        .with_spacer(10.0)
+        .with_child(
+            widget::Flex::row()
+                .with_flex_child(widget::Label::new("Presence Dot Size:").align_right()
+                , 0.7)
+                .with_default_spacer()
+                .with_flex_child(
+                    widget::Slider::new().with_range(4.0, 20.0).with_step(0.5)
+                    .lens(LayoutSettings::presence_dot_size)
+                , 1.0)
+                .with_flex_child(widget::Label::new(
+                    |data: &LayoutSettings, _: &_| {format!("{:.1}", data.presence_dot_size)}),
+                    0.3)
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+                .disabled_if(|data, _| !data.show_presence)
+        )
+        .with_spacer(10.0)
         .with_child(
             widget::Flex::row()
                 .with_flex_child(
@@ -325,9 +430,6 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::RadioGroup::column(DATETIME_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
                         .lens(LayoutSettings::datetime_format)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -341,9 +443,6 @@ fn build_advanced_layout_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::RadioGroup::column(TIME_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
                         .lens(LayoutSettings::side_time_format)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -360,9 +459,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::RadioGroup::column(TAIL_SHAPE_OPTIONS)
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
                         .lens(LayoutSettings::chat_bubble_tail_shape)
                 , 1.3)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -375,9 +471,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 12.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::chat_bubble_radius)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -393,9 +486,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(2.0, 12.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::chat_bubble_tail_size)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -410,9 +500,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Switch::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::left_bubble_flipped)
             , 1.3)
             .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -424,9 +511,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Switch::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::right_bubble_flipped)
             , 1.3)
             .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -439,9 +523,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 15.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::bubble_padding)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -458,9 +539,6 @@ fn build_advanced_bubble_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Switch::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::show_self_pic)
             , 1.3)
             .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -481,9 +559,6 @@ fn build_advanced_irc_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(100.0, 1000.0).with_step(5.0)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::irc_stack_width)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -499,9 +574,6 @@ fn build_advanced_irc_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(20.0, 250.0).with_step(1.0)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::irc_header_width)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -521,9 +593,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(-15.0, 20.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::chat_picture_spacing)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -539,9 +608,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 10.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::metadata_content_spacing)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -557,9 +623,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 28.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::single_message_spacing)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -575,9 +638,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 28.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::group_spacing)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -593,9 +653,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 10.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::left_spacing)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -611,9 +668,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
                 .with_default_spacer()
                 .with_flex_child(
                     widget::Slider::new().with_range(0.0, 30.0).with_step(0.5)
-                    .on_click( |ctx: &mut EventCtx, _, _ | {
-                        ui_changed_callback(ctx);
-                    })
                     .lens(LayoutSettings::left_meta_offset)
                 , 0.9)
                 .with_flex_child(widget::Label::new(
@@ -628,9 +682,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Switch::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::show_left_line)
             , 1.3)
             .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -642,9 +693,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Switch::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::align_to_picture)
             , 1.3)
             .cross_axis_alignment(widget::CrossAxisAlignment::Start)
@@ -660,9 +708,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Stepper::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::content_font_size)
             , 0.9)
             .with_flex_child(
@@ -678,9 +723,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Stepper::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::sender_font_size)
             , 0.9)
             .with_flex_child(
@@ -696,9 +738,6 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
             .with_default_spacer()
             .with_flex_child(
                 widget::Stepper::new()
-                .on_click( |ctx: &mut EventCtx, _, _ | {
-                    ui_changed_callback(ctx);
-                })
                 .lens(LayoutSettings::datetime_font_size)
             , 0.9)
             .with_flex_child(
@@ -710,20 +749,11 @@ fn build_advanced_sizing_settings() -> impl Widget<LayoutSettings> {
 }
 
 
-fn build_advanced_settings() -> impl Widget<LayoutSettings> {
-    widget::Flex::column()
+fn build_advanced_settings(recent_colors: &RecentColors) -> impl Widget<LayoutSettings> {
+    widget::Scroll::new(widget::Flex::column()
         .with_child(
-            widget::Flex::row()
-                .with_child(
-                    widget::Label::new("Layout Settings")
-                        .with_text_size(20.0).padding(8.0).align_left()
-                )
-                .with_child(
-                    widget::Button::new("Refresh Window")
-                        .on_click( |ctx: &mut EventCtx, _, _ | {
-                            ui_changed_callback(ctx);
-                        })
-                )
+            widget::Label::new("Layout Settings")
+                .with_text_size(20.0).padding(8.0).align_left()
         )
         .with_default_spacer()
         .with_child(
@@ -743,15 +773,143 @@ fn build_advanced_settings() -> impl Widget<LayoutSettings> {
                 .with_flex_child(build_advanced_sizing_settings(), 1.0)
                 .cross_axis_alignment(widget::CrossAxisAlignment::Start)
         )
-        
+        .with_spacer(30.0)
+        .with_child(build_advanced_color_settings(recent_colors))
+    ).vertical()
+    .controller(PictureMaskController)
+}
+
+/// Loads the SVG chosen with the "Load SVG Mask..." button, and switches to the custom mask shape.
+struct PictureMaskController;
+
+impl<W: Widget<LayoutSettings>> widget::Controller<LayoutSettings, W> for PictureMaskController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut LayoutSettings, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(PICTURE_MASK_SELECTED) => {
+                let file_info = cmd.get_unchecked(PICTURE_MASK_SELECTED);
+                let mask = std::fs::read(file_info.path())
+                    .map_err(|err| err.to_string())
+                    .and_then(|svg_data| shape_paths::load_svg_mask(&svg_data));
+                match mask {
+                    Ok(mask) => {
+                        data.picture_mask = Some(Arc::new(mask));
+                        data.picture_shape = PictureShape::CustomMask;
+                    },
+                    Err(err) => {
+                        error!("Failed to load picture mask {:?}: {}", file_info.path(), err);
+                    }
+                }
+                ctx.set_handled();
+            },
+            _ => child.event(ctx, event, data, env),
+        }
+    }
 }
 
-fn ui_changed_callback(ctx: &mut EventCtx) {
-    // Signal to all timeline widgets to refresh
-    ctx.submit_command(crate::REFRESH_UI_SELECTOR.to(druid::Target::Global));
+fn build_advanced_color_settings(recent_colors: &RecentColors) -> impl Widget<LayoutSettings> {
+    widget::Flex::row()
+        .with_flex_child(
+            widget::Flex::column()
+                .with_child(widget::Label::new("Other Users' Colors"))
+                .with_spacer(20.0)
+                .with_child(build_color_setting("Sender:", LayoutSettings::sender_color, recent_colors))
+                .with_spacer(10.0)
+                .with_child(build_color_setting("Date/Time:", LayoutSettings::datetime_color, recent_colors))
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        , 1.0)
+        .with_flex_child(
+            widget::Flex::column()
+                .with_child(widget::Label::new("Self Colors"))
+                .with_spacer(20.0)
+                .with_child(build_color_setting("Sender:", LayoutSettings::self_sender_color, recent_colors))
+                .with_spacer(10.0)
+                .with_child(build_color_setting("Date/Time:", LayoutSettings::self_datetime_color, recent_colors))
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        , 1.0)
+        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
 }
 
-fn predefined_layout_selected(ctx: &mut EventCtx, layout: PredefinedLayout, settings: &mut LayoutSettings) {
-    settings.set_from_predefined_layout(layout);
-    ui_changed_callback(ctx);
-}
\ No newline at end of file
+fn build_theme_settings(recent_colors: &RecentColors) -> impl Widget<LayoutSettings> {
+    widget::Scroll::new(widget::Flex::column()
+        .with_child(
+            widget::Label::new("Theme")
+                .with_text_size(20.0).padding(8.0).align_left()
+        )
+        .with_default_spacer()
+        .with_child(
+            widget::Flex::row()
+                .with_flex_child(
+                    widget::Label::new("Theme").align_right()
+                , 0.7)
+                .with_default_spacer()
+                .with_flex_child(
+                    widget::Flex::row()
+                        .with_child(
+                            widget::Button::new("Dark")
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_theme(PredefinedTheme::Dark);
+                                })
+                        )
+                        .with_default_spacer()
+                        .with_child(
+                            widget::Button::new("Light")
+                                .on_click( |_, data: &mut LayoutSettings, _ | {
+                                    data.set_from_predefined_theme(PredefinedTheme::Light);
+                                })
+                        )
+                , 3.3)
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        )
+        .with_spacer(20.0)
+        .with_child(
+            widget::Flex::row()
+                .with_flex_child(
+                    widget::Flex::column()
+                        .with_child(widget::Label::new("Bubbles"))
+                        .with_spacer(20.0)
+                        .with_child(build_color_setting("Other Bubble:", ThemeSettings::other_bubble_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Self Bubble:", ThemeSettings::self_bubble_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Hover Highlight:", ThemeSettings::hover_highlight_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Left Line:", ThemeSettings::left_line_color, recent_colors))
+                        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+                , 1.0)
+                .with_flex_child(
+                    widget::Flex::column()
+                        .with_child(widget::Label::new("Window"))
+                        .with_spacer(20.0)
+                        .with_child(build_color_setting("Background:", ThemeSettings::window_background_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Panels:", ThemeSettings::panel_background_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Inputs:", ThemeSettings::input_background_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Borders:", ThemeSettings::border_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Accent:", ThemeSettings::accent_color, recent_colors))
+                        .with_spacer(10.0)
+                        .with_child(build_color_setting("Text:", ThemeSettings::text_color, recent_colors))
+                        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+                , 1.0)
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+                .lens(LayoutSettings::theme)
+        )
+    ).vertical()
+}
+
+/// A labeled row with a color picker for the color
+fn build_color_setting<T: Data>(name: &str, color_lens: impl Lens<T, SimpleColor> + 'static,
+    recent_colors: &RecentColors) -> impl Widget<T>
+{
+    widget::Flex::row()
+        .with_flex_child(widget::Label::new(name.to_string()).align_right()
+        , 0.7)
+        .with_default_spacer()
+        .with_flex_child(
+            color_picker_widget::build_color_picker(recent_colors.clone())
+        , 1.3)
+        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        .lens(color_lens)
+}
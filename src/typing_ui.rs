@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use druid::{Data, Env, Event, EventCtx, TimerToken, UpdateCtx, Widget};
+use druid::widget;
+use crate::AppState;
+use crate::core_interface::{CoreRequest, CORE_SUPPORT, SEND_TO_CORE};
+
+/// How long after the last edit we tell the core we stopped typing
+const TYPING_STOP_DELAY: Duration = Duration::from_secs(4);
+/// Protocols forget that someone is typing after a while, so we tell them again this often while we are
+const TYPING_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Who is typing, like "Alice is typing" or "Alice and 2 others are typing".
+/// The indicator draws the dots after it.
+pub(crate) fn typing_text(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => format!("{} is typing", name),
+        [first, second] => format!("{} and {} are typing", first, second),
+        [first, others @ ..] => format!("{} and {} others are typing", first, others.len()),
+    }
+}
+
+/// Tells the core when we start typing in the composer and when we stop. Starting is sent
+/// on the first edit, and again now and then while the edits keep coming. Stopping is sent
+/// once the composer is emptied, which sending does too, or after a pause in the edits.
+pub(crate) struct TypingNotificationController {
+    /// When we last told the core we're typing, or None if we told it we stopped
+    typing_sent_at: Option<Instant>,
+    stop_timer: TimerToken,
+}
+
+impl TypingNotificationController {
+    pub fn new() -> Self {
+        TypingNotificationController { typing_sent_at: None, stop_timer: TimerToken::INVALID }
+    }
+}
+
+impl<W: Widget<AppState>> widget::Controller<AppState, W> for TypingNotificationController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        if let Event::Timer(token) = event {
+            if *token == self.stop_timer {
+                self.stop_timer = TimerToken::INVALID;
+                if self.typing_sent_at.take().is_some() {
+                    ctx.submit_command(SEND_TO_CORE.with(CoreRequest::SetTyping { is_typing: false }));
+                }
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+
+    // Watches the text here rather than in event, since the emoji picker, the completions
+    // and the send button change it from outside the text box
+    fn update(&mut self, child: &mut W, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        if CORE_SUPPORT.typing_notifications && !old_data.text_edit.same(&data.text_edit) {
+            if data.text_edit.is_empty() {
+                self.stop_timer = TimerToken::INVALID;
+                if self.typing_sent_at.take().is_some() {
+                    ctx.submit_command(SEND_TO_CORE.with(CoreRequest::SetTyping { is_typing: false }));
+                }
+            } else {
+                let needs_refresh = self.typing_sent_at.is_none_or(|sent_at| sent_at.elapsed() >= TYPING_REFRESH_INTERVAL);
+                if needs_refresh {
+                    ctx.submit_command(SEND_TO_CORE.with(CoreRequest::SetTyping { is_typing: true }));
+                    self.typing_sent_at = Some(Instant::now());
+                }
+                self.stop_timer = ctx.request_timer(TYPING_STOP_DELAY);
+            }
+        }
+        child.update(ctx, old_data, data, env);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn nobody_typing_shows_nothing() {
+        assert_eq!(typing_text(&[]), "");
+    }
+
+    #[test]
+    fn one_or_two_people_typing_are_named() {
+        assert_eq!(typing_text(&names(&["Alice"])), "Alice is typing");
+        assert_eq!(typing_text(&names(&["Alice", "Bob"])), "Alice and Bob are typing");
+    }
+
+    #[test]
+    fn more_people_typing_are_counted() {
+        assert_eq!(typing_text(&names(&["Alice", "Bob", "Carol"])), "Alice and 2 others are typing");
+        assert_eq!(typing_text(&names(&["Alice", "Bob", "Carol", "Dan"])), "Alice and 3 others are typing");
+    }
+}
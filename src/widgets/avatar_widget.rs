@@ -0,0 +1,152 @@
+use druid::kurbo::{Circle, Rect};
+use druid::piet::{Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::{widget, WidgetPod};
+use tracing::error;
+use crate::LayoutSettings;
+use crate::data::avatar_data::Avatar;
+use crate::data::conversation_data::ConversationData;
+use crate::data::presence_data::Presence;
+use crate::helper::layout_settings::WithSettings;
+use crate::helper::{avatar_cache, shape_paths};
+
+thread_local! {
+    // Parsed once, since every timeline item needs it
+    static DEFAULT_AVATAR_SVG: widget::SvgData = match include_str!("../assets/default_profile_pic.svg").parse::<widget::SvgData>() {
+        Ok(svg) => svg,
+        Err(err) => {
+            error!("{}", err);
+            error!("Using an empty SVG instead.");
+            widget::SvgData::default()
+        }
+    };
+}
+
+/// Initials take up this fraction of the picture size
+const INITIALS_SIZE_RATIO: f64 = 0.4;
+/// The width of the ring around the presence dot that separates it from the picture
+const PRESENCE_RING_WIDTH: f64 = 2.0;
+
+/// Paints a user's avatar clipped to the picture shape, with their presence dot.
+///
+/// Owned by the widgets that show avatars, which forward their events to it,
+/// so the default picture can be drawn with the `Svg` widget.
+pub struct AvatarPainter<T> {
+    /// Painted in the picture's place when the user has no avatar
+    default_avatar: WidgetPod<T, widget::Svg>,
+}
+
+impl<T: Data> AvatarPainter<T> {
+    pub fn new() -> Self {
+        AvatarPainter {
+            default_avatar: WidgetPod::new(
+                widget::Svg::new(DEFAULT_AVATAR_SVG.with(|svg| svg.clone()))
+            ),
+        }
+    }
+
+    pub fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        self.default_avatar.event(ctx, event, data, env);
+    }
+
+    pub fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
+        self.default_avatar.lifecycle(ctx, event, data, env);
+    }
+
+    pub fn update(&mut self, ctx: &mut UpdateCtx, data: &T, env: &Env) {
+        self.default_avatar.update(ctx, data, env);
+    }
+
+    /// Positioned when painting, since that's where the picture's position is worked out
+    pub fn layout(&mut self, ctx: &mut LayoutCtx, picture_size: f64, data: &T, env: &Env) {
+        let picture_size = Size::new(picture_size, picture_size);
+        self.default_avatar.layout(ctx, &BoxConstraints::tight(picture_size), data, env);
+    }
+
+    pub fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env, settings: &LayoutSettings,
+        user_id: u32, avatar: &Avatar, presence: Presence, pic_rect: Rect)
+    {
+        let picture_path = shape_paths::make_picture_path(settings, pic_rect);
+        ctx.with_save(|ctx| { // Makes it so the clip doesn't mess up the following draws
+            ctx.clip(picture_path.clone());
+            match avatar {
+                Avatar::Image(image) => {
+                    let piet_image = avatar_cache::get_avatar_image(ctx, user_id, image, settings.picture_size);
+                    ctx.draw_image(&piet_image, pic_rect, druid::piet::InterpolationMode::Bilinear);
+                },
+                Avatar::Initials { initials, color } => {
+                    ctx.fill(pic_rect, &color.to_druid_color());
+                    let initials_layout = ctx.text().new_text_layout(initials.clone())
+                        .font(druid::FontFamily::SYSTEM_UI, settings.picture_size * INITIALS_SIZE_RATIO)
+                        .text_color(druid::Color::WHITE)
+                        .build();
+                    match initials_layout {
+                        Ok(initials_layout) => {
+                            let text_origin = pic_rect.center() - initials_layout.size().to_vec2() / 2.0;
+                            ctx.draw_text(&initials_layout, text_origin);
+                        },
+                        Err(err) => error!("Failed to lay out initials: {}", err),
+                    }
+                },
+                Avatar::Default => {
+                    ctx.transform(druid::Affine::translate(pic_rect.origin().to_vec2()));
+                    self.default_avatar.paint_raw(ctx, data, env);
+                },
+            }
+        });
+
+        if settings.show_presence {
+            if let Some(dot_color) = presence.get_dot_color() {
+                let dot = Circle::new(
+                    shape_paths::get_outline_point(&picture_path, pic_rect),
+                    settings.presence_dot_size / 2.0
+                );
+                ctx.fill(dot, &dot_color);
+                ctx.stroke(dot, &env.get(druid::theme::WINDOW_BACKGROUND_COLOR), PRESENCE_RING_WIDTH);
+            }
+        }
+    }
+}
+
+/// Just an avatar, sized to the picture size in the settings
+pub struct AvatarWidget {
+    painter: AvatarPainter<WithSettings<ConversationData>>,
+}
+
+impl AvatarWidget {
+    pub fn new() -> Self {
+        AvatarWidget { painter: AvatarPainter::new() }
+    }
+}
+
+impl Widget<WithSettings<ConversationData>> for AvatarWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<ConversationData>, env: &Env) {
+        self.painter.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<ConversationData>, env: &Env) {
+        self.painter.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<ConversationData>, data: &WithSettings<ConversationData>, env: &Env) {
+        if !old_data.0.same(&data.0) {
+            ctx.request_layout();
+        } else if !old_data.1.same(&data.1) {
+            ctx.request_paint();
+        }
+        self.painter.update(ctx, data, env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &WithSettings<ConversationData>, env: &Env) -> Size {
+        let picture_size = data.0.picture_size;
+        self.painter.layout(ctx, picture_size, data, env);
+        bc.constrain(Size::new(picture_size, picture_size))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<ConversationData>, env: &Env) {
+        let (settings, conversation) = data;
+        let pic_rect = Rect::from_origin_size(druid::Point::ORIGIN, Size::new(settings.picture_size, settings.picture_size));
+        self.painter.paint(ctx, data, env, settings, conversation.user_id,
+            &conversation.avatar, conversation.presence, pic_rect);
+    }
+}
@@ -1,29 +1,51 @@
 use std::time::Duration;
 
-use druid::{WindowDesc, Widget, WidgetPod, WidgetExt, EventCtx, im, Event, TimerToken, Screen, Monitor, Size, ImageBuf};
-use druid::widget;
-use crate::{AppState, Message, MessageGroup};
+use druid::{WindowDesc, Widget, WidgetPod, WidgetExt, EventCtx, im, Event, TimerToken, Screen, Monitor, Size};
+use druid::{widget, lens};
+use crate::{AppState, Message, MessageGroup, SELF_USER_ID};
 use super::timeline_item_widget;
-use tracing::error;
+use super::typing_indicator_widget::TypingIndicatorWidget;
+use tracing::{error, warn};
+use crate::attachment_ui;
+use crate::emoji_ui;
+use crate::mention_ui;
+use crate::core_interface::{CoreRequest, CORE_SUPPORT, SEND_TO_CORE};
+use crate::message_ui;
+use crate::typing_ui;
 use crate::settings_ui::build_settings_ui;
+use crate::helper::layout_settings::WithSettings;
+use crate::helper::mentions;
 
 pub struct ChatWindowWidget {
     header: WidgetPod<AppState, widget::Container<AppState>>,
     timeline: WidgetPod<AppState, Box<dyn druid::Widget<AppState>>>,
+    /// Pinned under the timeline while others are typing
+    typing_indicator: WidgetPod<AppState, TypingIndicatorWidget>,
     footer: WidgetPod<AppState, widget::Flex<AppState>>,
+    /// Drawn over the bottom right of the timeline while it's open
+    emoji_picker: WidgetPod<AppState, Box<dyn Widget<AppState>>>,
     location_timer_token: TimerToken,
+    /// A timer for each edit waiting for the core, with the message's id and the new text
+    pending_edit_timers: Vec<(TimerToken, u32, String)>,
 }
 
 const LOCATION_CHECK_TIMER_INTERVAL: Duration = Duration::from_millis(200);
 const LOCATION_MOVE_INTERVAL: Duration = Duration::from_millis(16);
+/// Space between the emoji picker and the edges of the window and the footer
+const EMOJI_PICKER_MARGIN: f64 = 4.0;
+/// The settings window can be made smaller than it opens, down to this
+const SETTINGS_MIN_SIZE: (f64, f64) = (900.0, 500.0);
 
 impl ChatWindowWidget {
     pub fn new() -> ChatWindowWidget {
         ChatWindowWidget {
             header: Self::build_title(),
             timeline: Self::build_timeline(),
+            typing_indicator: WidgetPod::new(TypingIndicatorWidget::new()),
             footer: Self::build_footer(),
-            location_timer_token: TimerToken::INVALID
+            emoji_picker: WidgetPod::new(emoji_ui::build_emoji_picker().boxed()),
+            location_timer_token: TimerToken::INVALID,
+            pending_edit_timers: Vec::new(),
         }
     }
 
@@ -50,7 +72,7 @@ impl ChatWindowWidget {
                     widget::Click::new(on_settings_icon_click)
                 )
             )
-            .background(druid::theme::BACKGROUND_LIGHT)
+            .background(crate::PANEL_BACKGROUND_KEY)
         )
     }
 
@@ -65,7 +87,11 @@ impl ChatWindowWidget {
             )
             .vertical()
             .expand()
-            .lens(AppState::timeline_data)
+            // Pair the timeline with the settings snapshot so every item can lay out from it
+            .lens(lens::Map::new(
+                |data: &AppState| (data.layout_settings.clone(), data.timeline_data.clone()),
+                |data: &mut AppState, (_, timeline): WithSettings<im::Vector<MessageGroup>>| data.timeline_data = timeline,
+            ))
             .boxed()
         )
     }
@@ -79,22 +105,93 @@ impl ChatWindowWidget {
                 widget::SvgData::default()
             }
         };
+        let emoji_svg = match include_str!("../assets/emoji.svg").parse::<widget::SvgData>() {
+            Ok(svg) => svg,
+            Err(err) => {
+                error!("{}", err);
+                error!("Using an empty SVG instead.");
+                widget::SvgData::default()
+            }
+        };
+        let attach_svg = match include_str!("../assets/attach.svg").parse::<widget::SvgData>() {
+            Ok(svg) => svg,
+            Err(err) => {
+                error!("{}", err);
+                error!("Using an empty SVG instead.");
+                widget::SvgData::default()
+            }
+        };
 
-        WidgetPod::new(widget::Flex::row()
+        // Shown while composing a reply. The x cancels it.
+        let reply_banner = widget::Either::new(
+            |data: &AppState, _env: &_| data.replying_to.is_some(),
+            widget::Flex::row()
+                .with_flex_child(
+                    widget::Label::new(|data: &AppState, _env: &_| match &data.replying_to {
+                        Some(reply) => format!("Replying to {}: {}", reply.sender_name, reply.first_line),
+                        None => String::new(),
+                    })
+                    .with_line_break_mode(widget::LineBreaking::Clip)
+                    .padding(4.0)
+                    .expand_width(),
+                1.0)
+                .with_child(
+                    widget::Button::new("✕").on_click(|_ctx: &mut EventCtx, data: &mut AppState, _env: &_| {
+                        data.replying_to = None;
+                    })
+                )
+                .background(crate::PANEL_BACKGROUND_KEY),
+            widget::SizedBox::empty()
+        );
+
+        let composer = widget::Flex::row()
+            .with_child(
+                widget::ControllerHost::new(
+                    widget::Svg::new(attach_svg).fix_height(20.0).padding(5.0),
+                    widget::Click::new(|ctx: &mut EventCtx, _data: &mut AppState, _env: &_| {
+                        attachment_ui::open_attachment_dialog(ctx);
+                    })
+                )
+            )
             .with_flex_child(
                 widget::TextBox::multiline()
                     .with_placeholder("Message...")
                     .lens(AppState::text_edit)
+                    .controller(EditLastMessageController)
+                    .controller(attachment_ui::PasteImageController)
+                    .controller(emoji_ui::shortcode_completions().controller())
+                    .controller(mention_ui::mention_completions().controller())
+                    .controller(typing_ui::TypingNotificationController::new())
                     .padding(1.0)
                     .expand_width(),
             1.0)
+            .with_child(
+                widget::ControllerHost::new(
+                    widget::Svg::new(emoji_svg).fix_height(20.0).padding(5.0),
+                    widget::Click::new(|_ctx: &mut EventCtx, data: &mut AppState, _env: &_| {
+                        if data.emoji_picker.is_open {
+                            data.emoji_picker.close();
+                        } else {
+                            data.emoji_picker.is_open = true;
+                        }
+                    })
+                )
+            )
             .with_child(
                 widget::ControllerHost::new(
                     widget::Svg::new(send_svg).fix_height(25.0).padding(5.0),
                     widget::Click::new(on_send_icon_click)
                 )
                 
-            )
+            );
+
+        WidgetPod::new(widget::Flex::column()
+            .with_child(reply_banner)
+            .with_child(attachment_ui::build_attachment_tray())
+            .with_child(emoji_ui::shortcode_completions().build())
+            .with_child(mention_ui::mention_completions().build())
+            .with_child(composer)
+            .cross_axis_alignment(widget::CrossAxisAlignment::Fill)
         )
     }
 
@@ -138,24 +235,96 @@ impl Widget<AppState> for ChatWindowWidget {
                     }
                     return; // Handled. No need to run the event to every other widget.
                 }
+                if let Some(index) = self.pending_edit_timers.iter().position(|(token, _, _)| token == id) {
+                    let (_, message_id, new_text) = self.pending_edit_timers.remove(index);
+                    // Still waiting for this edit, rather than one saved after it
+                    let is_still_pending = data.get_message(message_id)
+                        .is_some_and(|message| message.pending_edit.as_ref() == Some(&new_text));
+                    if is_still_pending {
+                        warn!("The core didn't confirm the edit of message {} in time.", message_id);
+                        data.fail_pending_edit(message_id);
+                    }
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            Event::Command(cmd) => {
+                if let Some(message_id) = cmd.get(message_ui::REPLY_TO_MESSAGE) {
+                    data.replying_to = data.make_reply_reference(*message_id);
+                    ctx.set_handled();
+                    return;
+                }
+                if let Some(message_id) = cmd.get(message_ui::EDIT_MESSAGE) {
+                    data.start_editing(*message_id);
+                    ctx.set_handled();
+                    return;
+                }
+                if let Some(message_id) = cmd.get(message_ui::ADD_REACTION) {
+                    data.emoji_picker.open_for_reaction(*message_id);
+                    ctx.set_handled();
+                    return;
+                }
+                if let Some((message_id, new_text)) = cmd.get(message_ui::SAVE_MESSAGE_EDIT) {
+                    let token = ctx.request_timer(message_ui::PENDING_EDIT_TIMEOUT);
+                    self.pending_edit_timers.push((token, *message_id, new_text.clone()));
+                }
+                if message_ui::forward_message_command(ctx, cmd) {
+                    return;
+                }
+                if attachment_ui::handle_attachment_command(ctx, cmd, data) {
+                    return;
+                }
+            }
+            Event::KeyDown(key) if key.key == druid::KbKey::Escape && data.emoji_picker.is_open => {
+                data.emoji_picker.close();
+                ctx.set_handled();
+                return;
+            }
+            Event::MouseDown(mouse) if data.emoji_picker.is_open => {
+                // Clicking outside of the picker closes it. The footer is left out, since its emoji button toggles it.
+                let picker_rect = self.emoji_picker.layout_rect();
+                if !picker_rect.contains(mouse.pos) && !self.footer.layout_rect().contains(mouse.pos) {
+                    data.emoji_picker.close();
+                }
             }
             _ => (),
         }
+        if data.emoji_picker.is_open {
+            // On top, so it gets the events first, and the timeline under it doesn't get its clicks
+            self.emoji_picker.event(ctx, event, data, env);
+            let is_over_picker = match event {
+                Event::MouseDown(mouse) | Event::MouseUp(mouse) | Event::MouseMove(mouse) | Event::Wheel(mouse) => {
+                    self.emoji_picker.layout_rect().contains(mouse.pos)
+                },
+                _ => false,
+            };
+            if ctx.is_handled() || is_over_picker {
+                return;
+            }
+        }
         self.header.event(ctx, event, data, env);
         self.timeline.event(ctx, event, data, env);
+        self.typing_indicator.event(ctx, event, data, env);
         self.footer.event(ctx, event, data, env);
     }
 
     fn lifecycle(&mut self, ctx: &mut druid::LifeCycleCtx, event: &druid::LifeCycle, data: &AppState, env: &druid::Env) {
         self.header.lifecycle(ctx, event, data, env);
         self.timeline.lifecycle(ctx, event, data, env);
+        self.typing_indicator.lifecycle(ctx, event, data, env);
         self.footer.lifecycle(ctx, event, data, env);
+        self.emoji_picker.lifecycle(ctx, event, data, env);
     }
 
-    fn update(&mut self, ctx: &mut druid::UpdateCtx, _old_data: &AppState, data: &AppState, env: &druid::Env) {
+    fn update(&mut self, ctx: &mut druid::UpdateCtx, old_data: &AppState, data: &AppState, env: &druid::Env) {
+        if old_data.emoji_picker.is_open != data.emoji_picker.is_open {
+            ctx.request_paint();
+        }
         self.header.update(ctx, data, env);
         self.timeline.update(ctx, data, env);
+        self.typing_indicator.update(ctx, data, env);
         self.footer.update(ctx, data, env);
+        self.emoji_picker.update(ctx, data, env);
     }
 
     fn layout(&mut self, ctx: &mut druid::LayoutCtx, bc: &druid::BoxConstraints, data: &AppState, env: &druid::Env) -> druid::Size {
@@ -168,13 +337,29 @@ impl Widget<AppState> for ChatWindowWidget {
             druid::Size::new(0.0, 0.0), druid::Size::new(bc.max().width, 0.0f64.max(bc.max().height - header_size.height - 100.0)));
         let footer_size = self.footer.layout(ctx, &footer_max_size, data, env);
 
+        // Takes no height while nobody is typing
+        let typing_size = self.typing_indicator.layout(ctx, &druid::BoxConstraints::new(
+            druid::Size::new(bc.max().width, 0.0), druid::Size::new(bc.max().width, f64::INFINITY)), data, env);
+
         let content_max_size = druid::BoxConstraints::new(
-            druid::Size::new(0.0, 0.0), druid::Size::new(bc.max().width, 0.0f64.max(bc.max().height - header_size.height - footer_size.height)));
+            druid::Size::new(0.0, 0.0), druid::Size::new(bc.max().width, 0.0f64.max(bc.max().height - header_size.height - footer_size.height - typing_size.height)));
 
         let timeline_size = self.timeline.layout(ctx, &content_max_size, data, env);
 
         self.timeline.set_origin(ctx, druid::Point::new(0.0, header_size.height));
-        self.footer.set_origin(ctx, druid::Point::new(0.0, header_size.height + timeline_size.height));
+        let typing_y = header_size.height + timeline_size.height;
+        self.typing_indicator.set_origin(ctx, druid::Point::new(0.0, typing_y));
+        self.footer.set_origin(ctx, druid::Point::new(0.0, typing_y + typing_size.height));
+
+        // Laid out even while closed, so it's ready to paint when opened.
+        // It sits above the right end of the footer, where the emoji button is.
+        let (picker_width, picker_height) = emoji_ui::EMOJI_PICKER_SIZE;
+        let picker_size = druid::Size::new(picker_width.min(bc.max().width), picker_height.min(timeline_size.height + typing_size.height));
+        self.emoji_picker.layout(ctx, &druid::BoxConstraints::tight(picker_size), data, env);
+        self.emoji_picker.set_origin(ctx, druid::Point::new(
+            bc.max().width - picker_size.width - EMOJI_PICKER_MARGIN,
+            typing_y + typing_size.height - picker_size.height - EMOJI_PICKER_MARGIN
+        ));
 
         druid::Size::new(bc.max().width, bc.max().height)
     }
@@ -182,33 +367,80 @@ impl Widget<AppState> for ChatWindowWidget {
     fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &AppState, env: &druid::Env) {
         self.header.paint(ctx, data, env);
         self.timeline.paint(ctx, data, env);
+        self.typing_indicator.paint(ctx, data, env);
         self.footer.paint(ctx, data, env);
+        if data.emoji_picker.is_open {
+            self.emoji_picker.paint(ctx, data, env);
+        }
     }
 }
 
 
-fn on_send_icon_click(_ctx: &mut EventCtx, state: &mut AppState, _env: &druid::Env) {
-    println!("Send click");
+/// Pressing Up in the empty composer edits our last message
+struct EditLastMessageController;
+
+impl<W: Widget<AppState>> widget::Controller<AppState, W> for EditLastMessageController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &druid::Env) {
+        if let Event::KeyDown(key) = event {
+            if key.key == druid::KbKey::ArrowUp && CORE_SUPPORT.edit_messages && data.text_edit.is_empty() {
+                if let Some(message_id) = data.last_own_message_id() {
+                    data.start_editing(message_id);
+                    ctx.set_handled();
+                    return;
+                }
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+}
 
-    // Find which user is self
+fn on_send_icon_click(ctx: &mut EventCtx, state: &mut AppState, _env: &druid::Env) {
+    println!("Send click");
+    if state.text_edit.is_empty() && state.pending_attachments.is_empty() {
+        return;
+    }
 
     // TODO: Check to see if last thing in the timeline is a message from
     // self user to append to existing group.
+    let message_id = state.next_message_id();
+    let reply_to = state.replying_to.take();
+    // The plugin translates the mention tokens to its protocol's format
+    let text = mentions::encode_mentions(&state.text_edit, &state.composer_mentions);
+    let mention_names = state.mention_names(&text);
+    state.composer_mentions.clear();
+    let attachments = std::mem::take(&mut state.pending_attachments);
+    ctx.submit_command(SEND_TO_CORE.with(CoreRequest::SendMessage {
+        message_id,
+        text: text.clone(),
+        reply_to: reply_to.as_ref().map(|reply| reply.message_id),
+        attachments: attachments.iter().cloned().collect(),
+    }));
     state.timeline_data.push_back(
         MessageGroup {
             messages: im::vector![
                 Message {
-                    message: state.text_edit.to_string(),
+                    id: message_id,
+                    user_id: SELF_USER_ID,
+                    message: text,
                     position_in_group: 0,
-                    timestamp_epoch_seconds: chrono::offset::Local::now().timestamp()
+                    timestamp_epoch_seconds: chrono::offset::Local::now().timestamp(),
+                    reactions: im::vector![],
+                    reply_to,
+                    attachments,
+                    edits: im::vector![],
+                    deleted: false,
+                    edit_draft: None,
+                    pending_edit: None,
+                    mention_names,
                 }
             ],
-            user_id: 0 as u32,
-            profile_pic: ImageBuf::empty(),
+            user_id: SELF_USER_ID,
+            display_name: state.get_display_name(SELF_USER_ID),
+            avatar: state.get_avatar(SELF_USER_ID),
+            presence: state.get_presence(SELF_USER_ID),
         }
     );
-
-    //state.text_edit
+    state.text_edit = Default::default();
 }
 
 fn on_settings_icon_click(ctx: &mut EventCtx, state: &mut AppState, _env: &druid::Env) {
@@ -218,9 +450,14 @@ fn on_settings_icon_click(ctx: &mut EventCtx, state: &mut AppState, _env: &druid
         println!("Settings already open. Ignoring.");
     } else {
         state.settings_open = true; // Prevent it from being opened a second time
-        let settings_size = druid::Size::new(1400.0, 750.0);
-        let mut new_win = WindowDesc::new(build_settings_ui()).resizable(false);
-        new_win = new_win.window_size(settings_size);
+        // Wide enough for the settings and the preview side by side, but never bigger than the screen
+        let mut settings_size = druid::Size::new(1800.0, 750.0);
+        if let Some(monitor) = get_current_monitor(ctx) {
+            let working_area = monitor.virtual_work_rect();
+            settings_size = druid::Size::new(settings_size.width.min(working_area.width()), settings_size.height.min(working_area.height()));
+        }
+        let mut new_win = WindowDesc::new(build_settings_ui()).resizable(true);
+        new_win = new_win.window_size(settings_size).with_min_size(SETTINGS_MIN_SIZE);
         ctx.new_window(new_win);
     }
 }
@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use druid::kurbo::{Circle, Line};
+use druid::piet::{LinearGradient, UnitPoint};
+use druid::text::{Formatter, Selection, Validation, ValidationError};
+use druid::widget::prelude::*;
+use druid::{widget, Color, Point, Rect, Widget, WidgetExt};
+use crate::helper::layout_settings::SimpleColor;
+
+const SQUARE_SIZE: f64 = 90.0;
+const HUE_BAR_WIDTH: f64 = 14.0;
+const HUE_BAR_SPACING: f64 = 6.0;
+const SWATCH_SIZE: f64 = 16.0;
+const SWATCH_SPACING: f64 = 4.0;
+const SWATCHES_PER_ROW: usize = 4;
+const MAX_RECENT_COLORS: usize = 8;
+
+/// Colors recently picked in any color picker that shares this list.
+/// Most recent first.
+pub type RecentColors = Rc<RefCell<Vec<SimpleColor>>>;
+
+/// Builds a color picker made up of a saturation/value square with a hue
+/// slider, a hex entry, and swatches of recently picked colors.
+pub fn build_color_picker(recent_colors: RecentColors) -> impl Widget<SimpleColor> {
+    widget::Flex::row()
+        .with_child(HsvPicker::new(recent_colors.clone()))
+        .with_default_spacer()
+        .with_child(
+            widget::Flex::column()
+                .with_child(
+                    widget::TextBox::new()
+                        .with_formatter(HexColorFormatter)
+                        .update_data_while_editing(true)
+                        .fix_width(90.0)
+                )
+                .with_default_spacer()
+                .with_child(RecentSwatches::new(recent_colors))
+                .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+        )
+        .cross_axis_alignment(widget::CrossAxisAlignment::Start)
+}
+
+fn remember_color(recent_colors: &RecentColors, color: &SimpleColor) {
+    let mut recent_colors = recent_colors.borrow_mut();
+    recent_colors.retain(|recent| recent != color);
+    recent_colors.insert(0, color.clone());
+    recent_colors.truncate(MAX_RECENT_COLORS);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DragTarget {
+    Square,
+    HueBar,
+}
+
+/// The saturation/value square, with the hue slider to its right.
+struct HsvPicker {
+    /// Kept separately since it can't be recovered from grays
+    hue: f64,
+    drag_target: Option<DragTarget>,
+    recent_colors: RecentColors,
+}
+
+impl HsvPicker {
+    fn new(recent_colors: RecentColors) -> Self {
+        HsvPicker {
+            hue: 0.0,
+            drag_target: None,
+            recent_colors,
+        }
+    }
+
+    fn square_rect() -> Rect {
+        Rect::new(0.0, 0.0, SQUARE_SIZE, SQUARE_SIZE)
+    }
+
+    fn hue_bar_rect() -> Rect {
+        let x0 = SQUARE_SIZE + HUE_BAR_SPACING;
+        Rect::new(x0, 0.0, x0 + HUE_BAR_WIDTH, SQUARE_SIZE)
+    }
+
+    fn pick(&mut self, pos: Point, data: &mut SimpleColor) {
+        let (_, mut saturation, mut value) = data.to_hsv();
+        match self.drag_target {
+            Some(DragTarget::Square) => {
+                saturation = (pos.x / SQUARE_SIZE).clamp(0.0, 1.0);
+                value = 1.0 - (pos.y / SQUARE_SIZE).clamp(0.0, 1.0);
+            },
+            Some(DragTarget::HueBar) => {
+                self.hue = (pos.y / SQUARE_SIZE).clamp(0.0, 1.0) * 359.0;
+            },
+            None => return,
+        }
+        *data = SimpleColor::from_hsv(self.hue, saturation, value, data.a);
+    }
+}
+
+impl Widget<SimpleColor> for HsvPicker {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SimpleColor, _env: &Env) {
+        match event {
+            Event::MouseDown(mouse) => {
+                self.drag_target = if Self::square_rect().contains(mouse.pos) {
+                    Some(DragTarget::Square)
+                } else if Self::hue_bar_rect().contains(mouse.pos) {
+                    Some(DragTarget::HueBar)
+                } else {
+                    None
+                };
+                if self.drag_target.is_some() {
+                    ctx.set_active(true);
+                    self.pick(mouse.pos, data);
+                    ctx.request_paint();
+                }
+            },
+            Event::MouseMove(mouse) if ctx.is_active() => {
+                self.pick(mouse.pos, data);
+                ctx.request_paint();
+            },
+            Event::MouseUp(_) if ctx.is_active() => {
+                ctx.set_active(false);
+                self.drag_target = None;
+                remember_color(&self.recent_colors, data);
+                ctx.request_paint();
+            },
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &SimpleColor, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.hue = data.to_hsv().0;
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &SimpleColor, data: &SimpleColor, _env: &Env) {
+        if old_data != data {
+            let (hue, saturation, _) = data.to_hsv();
+            // Grays don't have a hue, so keep the one that was selected.
+            // Also keep it while dragging, since converting back and forth rounds it.
+            if saturation > 0.0 && self.drag_target.is_none() {
+                self.hue = hue;
+            }
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &SimpleColor, _env: &Env) -> Size {
+        bc.constrain(Size::new(SQUARE_SIZE + HUE_BAR_SPACING + HUE_BAR_WIDTH, SQUARE_SIZE))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &SimpleColor, _env: &Env) {
+        // Saturation goes left to right, and value goes top to bottom
+        let square = Self::square_rect();
+        ctx.fill(square, &SimpleColor::from_hsv(self.hue, 1.0, 1.0, 255).to_druid_color());
+        ctx.fill(square, &LinearGradient::new(
+            UnitPoint::LEFT, UnitPoint::RIGHT, (Color::WHITE, Color::WHITE.with_alpha(0.0))
+        ));
+        ctx.fill(square, &LinearGradient::new(
+            UnitPoint::TOP, UnitPoint::BOTTOM, (Color::BLACK.with_alpha(0.0), Color::BLACK)
+        ));
+
+        let hue_stops: Vec<Color> = (0..=6)
+            .map(|step| SimpleColor::from_hsv(step as f64 * 60.0, 1.0, 1.0, 255).to_druid_color())
+            .collect();
+        let hue_bar = Self::hue_bar_rect();
+        ctx.fill(hue_bar, &LinearGradient::new(UnitPoint::TOP, UnitPoint::BOTTOM, hue_stops.as_slice()));
+
+        // Markers for the current color
+        let (_, saturation, value) = data.to_hsv();
+        let marker = Circle::new(Point::new(saturation * SQUARE_SIZE, (1.0 - value) * SQUARE_SIZE), 4.0);
+        ctx.stroke(marker, &Color::BLACK, 2.5);
+        ctx.stroke(marker, &Color::WHITE, 1.0);
+        let hue_y = self.hue / 359.0 * SQUARE_SIZE;
+        let hue_marker = Line::new(Point::new(hue_bar.x0 - 2.0, hue_y), Point::new(hue_bar.x1 + 2.0, hue_y));
+        ctx.stroke(hue_marker, &Color::BLACK, 3.0);
+        ctx.stroke(hue_marker, &Color::WHITE, 1.0);
+    }
+}
+
+/// Clickable swatches of the recently picked colors
+struct RecentSwatches {
+    recent_colors: RecentColors,
+}
+
+impl RecentSwatches {
+    fn new(recent_colors: RecentColors) -> Self {
+        RecentSwatches { recent_colors }
+    }
+
+    fn swatch_rect(index: usize) -> Rect {
+        let column = (index % SWATCHES_PER_ROW) as f64;
+        let row = (index / SWATCHES_PER_ROW) as f64;
+        Rect::from_origin_size(
+            Point::new(column * (SWATCH_SIZE + SWATCH_SPACING), row * (SWATCH_SIZE + SWATCH_SPACING)),
+            Size::new(SWATCH_SIZE, SWATCH_SIZE),
+        )
+    }
+}
+
+impl Widget<SimpleColor> for RecentSwatches {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SimpleColor, _env: &Env) {
+        match event {
+            Event::MouseDown(mouse) => {
+                let clicked = self.recent_colors.borrow().iter().enumerate()
+                    .find(|(index, _)| Self::swatch_rect(*index).contains(mouse.pos))
+                    .map(|(_, color)| color.clone());
+                if let Some(color) = clicked {
+                    *data = color;
+                    remember_color(&self.recent_colors, data);
+                }
+            },
+            Event::MouseMove(_) => {
+                // Other pickers may have added colors since the last paint
+                ctx.request_paint();
+            },
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &SimpleColor, _env: &Env) {}
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &SimpleColor, data: &SimpleColor, _env: &Env) {
+        if old_data != data {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &SimpleColor, _env: &Env) -> Size {
+        let rows = (MAX_RECENT_COLORS + SWATCHES_PER_ROW - 1) / SWATCHES_PER_ROW;
+        bc.constrain(Size::new(
+            SWATCHES_PER_ROW as f64 * (SWATCH_SIZE + SWATCH_SPACING) - SWATCH_SPACING,
+            rows as f64 * (SWATCH_SIZE + SWATCH_SPACING) - SWATCH_SPACING,
+        ))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &SimpleColor, env: &Env) {
+        let border_color = env.get(druid::theme::BORDER_DARK);
+        for index in 0..MAX_RECENT_COLORS {
+            let swatch = Self::swatch_rect(index).to_rounded_rect(2.0);
+            if let Some(color) = self.recent_colors.borrow().get(index) {
+                ctx.fill(swatch, &color.to_druid_color());
+            }
+            ctx.stroke(swatch, &border_color, 1.0);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HexColorError;
+
+impl std::fmt::Display for HexColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expected a color like #1e90ff")
+    }
+}
+
+impl std::error::Error for HexColorError {}
+
+/// Formats colors as hex for the text box, and parses them back
+struct HexColorFormatter;
+
+impl Formatter<SimpleColor> for HexColorFormatter {
+    fn format(&self, value: &SimpleColor) -> String {
+        value.to_hex()
+    }
+
+    fn validate_partial_input(&self, input: &str, _sel: &Selection) -> Validation {
+        let digits = input.trim_start_matches('#');
+        if digits.len() <= 8 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            Validation::success()
+        } else {
+            Validation::failure(HexColorError)
+        }
+    }
+
+    fn value(&self, input: &str) -> Result<SimpleColor, ValidationError> {
+        SimpleColor::from_hex(input).ok_or_else(|| ValidationError::new(HexColorError))
+    }
+}
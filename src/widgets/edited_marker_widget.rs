@@ -0,0 +1,112 @@
+use druid::kurbo::{Rect, RoundedRect};
+use druid::text::TextLayout;
+use druid::widget::prelude::*;
+use druid::{Point, Vec2};
+use crate::Message;
+use crate::LayoutSettings;
+use crate::helper::helper_functions::{self, TimestampFormat};
+use crate::helper::layout_settings::WithSettings;
+
+const EDITED_TEXT: &str = "(edited)";
+const PENDING_TEXT: &str = "(saving...)";
+const TOOLTIP_PADDING: f64 = 4.0;
+const TOOLTIP_RADIUS: f64 = 3.0;
+/// Space between the marker and the tooltip above it
+const TOOLTIP_OFFSET: f64 = 2.0;
+
+/// A small "(edited)" under edited messages. Hovering it shows when the last edit was.
+/// Shows "(saving...)" while our edit waits for the core instead.
+/// Takes no space when the message hasn't been edited.
+pub struct EditedMarkerWidget {
+    marker_layout: TextLayout<String>,
+    tooltip_layout: TextLayout<String>,
+}
+
+impl EditedMarkerWidget {
+    pub fn new() -> Self {
+        EditedMarkerWidget {
+            marker_layout: TextLayout::from_text(EDITED_TEXT.to_string()),
+            tooltip_layout: TextLayout::new(),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        self.marker_layout.set_text_size(settings.datetime_font_size);
+        self.marker_layout.set_text_color(settings.datetime_color.to_druid_color());
+        self.tooltip_layout.set_text_size(settings.datetime_font_size);
+        self.tooltip_layout.set_text_color(settings.theme.text_color.to_druid_color());
+    }
+
+    fn is_shown(message: &Message) -> bool {
+        !message.deleted && (!message.edits.is_empty() || message.pending_edit.is_some())
+    }
+
+    fn update_text(&mut self, message: &Message) {
+        let marker_text = if message.pending_edit.is_some() { PENDING_TEXT } else { EDITED_TEXT };
+        self.marker_layout.set_text(marker_text.to_string());
+        if let Some(edited_at) = message.last_edited_at() {
+            self.tooltip_layout.set_text(format!(
+                "Edited {}",
+                helper_functions::timestamp_to_display_msg(edited_at, TimestampFormat::Full12)
+            ));
+        }
+    }
+}
+
+impl Widget<WithSettings<Message>> for EditedMarkerWidget {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut WithSettings<Message>, _env: &Env) {}
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        match event {
+            LifeCycle::WidgetAdded => {
+                self.apply_settings(&data.0);
+                self.update_text(&data.1);
+            },
+            LifeCycle::HotChanged(_) => ctx.request_paint(),
+            _ => {},
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        if !old_data.1.edits.same(&data.1.edits) || old_data.1.pending_edit != data.1.pending_edit {
+            self.update_text(&data.1);
+            ctx.request_layout();
+        }
+        if !old_data.0.same(&data.0) {
+            self.apply_settings(&data.0);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &WithSettings<Message>, env: &Env) -> Size {
+        if !Self::is_shown(&data.1) {
+            return bc.constrain(Size::ZERO);
+        }
+        self.marker_layout.rebuild_if_needed(ctx.text(), env);
+        self.tooltip_layout.rebuild_if_needed(ctx.text(), env);
+        bc.constrain(self.marker_layout.size())
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, _env: &Env) {
+        if !Self::is_shown(&data.1) {
+            return;
+        }
+        self.marker_layout.draw(ctx, Point::ORIGIN);
+        if ctx.is_hot() && data.1.pending_edit.is_none() {
+            let settings = data.0.clone();
+            let tooltip_size = self.tooltip_layout.size() + Size::new(TOOLTIP_PADDING * 2.0, TOOLTIP_PADDING * 2.0);
+            let tooltip_rect = Rect::from_origin_size(
+                Point::new(0.0, -tooltip_size.height - TOOLTIP_OFFSET),
+                tooltip_size
+            );
+            let tooltip_layout = self.tooltip_layout.clone();
+            // Drawn over the rest of the timeline, since it goes outside of the message
+            ctx.paint_with_z_index(1, move |ctx| {
+                let tooltip_shape = RoundedRect::from_rect(tooltip_rect, TOOLTIP_RADIUS);
+                ctx.fill(tooltip_shape, &settings.theme.panel_background_color.to_druid_color());
+                ctx.stroke(tooltip_shape, &settings.theme.border_color.to_druid_color(), 1.0);
+                tooltip_layout.draw(ctx, tooltip_rect.origin() + Vec2::new(TOOLTIP_PADDING, TOOLTIP_PADDING));
+            });
+        }
+    }
+}
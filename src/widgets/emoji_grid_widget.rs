@@ -0,0 +1,136 @@
+use druid::kurbo::{Rect, RoundedRect};
+use druid::text::TextLayout;
+use druid::widget::prelude::*;
+use druid::{Cursor, MouseButton, Point};
+use crate::AppState;
+use crate::helper::emoji_data;
+use crate::message_ui::TOGGLE_REACTION;
+
+const CELL_SIZE: f64 = 32.0;
+const CELL_RADIUS: f64 = 4.0;
+const EMOJI_TEXT_SIZE: f64 = 20.0;
+
+/// The emoji in the picker as a grid: the search results while searching, and the chosen category otherwise.
+/// Clicking one adds it to the composer, or reacts with it when the picker was opened from a message's menu.
+pub struct EmojiGridWidget {
+    emoji: Vec<(String, TextLayout<String>)>,
+    columns: usize,
+    hovered: Option<usize>,
+}
+
+impl EmojiGridWidget {
+    pub fn new() -> Self {
+        EmojiGridWidget { emoji: Vec::new(), columns: 1, hovered: None }
+    }
+
+    /// Only needed when the search, the category or the recent emoji change
+    fn rebuild_emoji(&mut self, data: &AppState) {
+        let picker = &data.emoji_picker;
+        let emoji: Vec<String> = if !picker.search.trim().is_empty() {
+            emoji_data::search(&picker.search).iter().map(|emoji| emoji.emoji.to_string()).collect()
+        } else {
+            match picker.category.and_then(|index| emoji_data::categories().get(index)) {
+                Some(category) => category.emoji.iter().map(|emoji| emoji.emoji.to_string()).collect(),
+                None => picker.recent.iter().cloned().collect(),
+            }
+        };
+        self.emoji = emoji.into_iter().map(|emoji| {
+            let mut layout = TextLayout::from_text(emoji.clone());
+            layout.set_text_size(EMOJI_TEXT_SIZE);
+            (emoji, layout)
+        }).collect();
+        self.hovered = None;
+    }
+
+    fn cell_rect(&self, index: usize) -> Rect {
+        let (row, column) = (index / self.columns, index % self.columns);
+        Rect::from_origin_size(Point::new(column as f64 * CELL_SIZE, row as f64 * CELL_SIZE), (CELL_SIZE, CELL_SIZE))
+    }
+
+    fn emoji_at(&self, pos: Point) -> Option<usize> {
+        if pos.x < 0.0 || pos.y < 0.0 || pos.x >= self.columns as f64 * CELL_SIZE {
+            return None;
+        }
+        let index = (pos.y / CELL_SIZE) as usize * self.columns + (pos.x / CELL_SIZE) as usize;
+        if index < self.emoji.len() { Some(index) } else { None }
+    }
+}
+
+impl Widget<AppState> for EmojiGridWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
+        match event {
+            Event::MouseMove(mouse) => {
+                let hovered = self.emoji_at(mouse.pos);
+                if hovered != self.hovered {
+                    self.hovered = hovered;
+                    ctx.request_paint();
+                }
+                if hovered.is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+            },
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(index) = self.emoji_at(mouse.pos) {
+                    let emoji = self.emoji[index].0.clone();
+                    match data.emoji_picker.reacting_to {
+                        Some(message_id) => {
+                            ctx.submit_command(TOGGLE_REACTION.with((message_id, emoji.clone())));
+                            data.emoji_picker.record_use(&emoji);
+                            data.emoji_picker.close();
+                        },
+                        None => data.insert_emoji(&emoji),
+                    }
+                    ctx.set_handled();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, _env: &Env) {
+        match event {
+            LifeCycle::WidgetAdded => self.rebuild_emoji(data),
+            LifeCycle::HotChanged(false) => {
+                self.hovered = None;
+                ctx.request_paint();
+            },
+            _ => {},
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        let (old_picker, picker) = (&old_data.emoji_picker, &data.emoji_picker);
+        if !old_picker.search.same(&picker.search)
+            || old_picker.category != picker.category
+            || !old_picker.recent.same(&picker.recent)
+        {
+            self.rebuild_emoji(data);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &AppState, env: &Env) -> Size {
+        self.columns = ((bc.max().width / CELL_SIZE).floor() as usize).max(1);
+        for (_, layout) in self.emoji.iter_mut() {
+            layout.rebuild_if_needed(ctx.text(), env);
+        }
+        let rows = self.emoji.len().div_ceil(self.columns);
+        bc.constrain(Size::new(self.columns as f64 * CELL_SIZE, rows as f64 * CELL_SIZE))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        if let Some(hovered) = self.hovered {
+            ctx.fill(
+                RoundedRect::from_rect(self.cell_rect(hovered), CELL_RADIUS),
+                &data.layout_settings.theme.hover_highlight_color.to_druid_color()
+            );
+        }
+        for (index, (_, layout)) in self.emoji.iter().enumerate() {
+            let cell = self.cell_rect(index);
+            let emoji_size = layout.size();
+            layout.draw(ctx, cell.center() - (emoji_size / 2.0).to_vec2());
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use druid::kurbo::{Rect, RoundedRect};
+use druid::text::TextLayout;
+use druid::widget::prelude::*;
+use druid::{Color, Cursor, FontDescriptor, FontWeight, MouseButton, Point, Selector};
+use crate::Message;
+use crate::LayoutSettings;
+use crate::attachment_ui::{OPEN_ATTACHMENT, SAVE_ATTACHMENT};
+use crate::data::attachment_data::{Attachment, TransferState};
+use crate::helper::helper_functions;
+use crate::helper::layout_settings::WithSettings;
+
+const CARD_MAX_WIDTH: f64 = 280.0;
+const CARD_PADDING: f64 = 8.0;
+const CARD_RADIUS: f64 = 4.0;
+const CARD_SPACING: f64 = 4.0;
+const CARD_BORDER_WIDTH: f64 = 1.0;
+const ICON_SIZE: f64 = 26.0;
+/// Space between the icon and the text
+const ICON_SPACING: f64 = 8.0;
+const LINE_SPACING: f64 = 2.0;
+const ACTION_SPACING: f64 = 12.0;
+const PROGRESS_BAR_HEIGHT: f64 = 3.0;
+const ERROR_COLOR: Color = Color::rgb8(0xE0, 0x55, 0x55);
+
+const SAVE_TEXT: &str = "Save as...";
+const OPEN_TEXT: &str = "Open";
+
+struct FileCard {
+    attachment_id: u32,
+    transfer: TransferState,
+    icon_layout: TextLayout<String>,
+    name_layout: TextLayout<String>,
+    status_layout: TextLayout<String>,
+    save_layout: TextLayout<String>,
+    open_layout: TextLayout<String>,
+    rect: Rect,
+    save_rect: Rect,
+    open_rect: Rect,
+}
+
+impl FileCard {
+    fn is_busy(&self) -> bool {
+        matches!(self.transfer, TransferState::Downloading { .. } | TransferState::ChoosingSaveLocation)
+    }
+
+    /// The action under the mouse, unless the card is busy
+    fn action_at(&self, pos: Point) -> Option<Selector<(u32, u32)>> {
+        if self.is_busy() {
+            None
+        } else if self.save_rect.contains(pos) {
+            Some(SAVE_ATTACHMENT)
+        } else if self.open_rect.contains(pos) {
+            Some(OPEN_ATTACHMENT)
+        } else {
+            None
+        }
+    }
+}
+
+/// A card for every attachment that isn't an image, with an icon for its type, its name,
+/// its size or download progress, and actions to save or open it. Failures show in place of the size.
+/// Takes no space when there are none.
+pub struct FileCardsWidget {
+    cards: Vec<FileCard>,
+}
+
+impl FileCardsWidget {
+    pub fn new() -> Self {
+        FileCardsWidget { cards: Vec::new() }
+    }
+
+    /// Only needed when the attachments change
+    fn rebuild_cards(&mut self, data: &WithSettings<Message>) {
+        let (settings, message) = data;
+        self.cards = message.attachments.iter()
+            .filter(|attachment| !attachment.is_image())
+            .map(|attachment| FileCard {
+                attachment_id: attachment.id,
+                transfer: attachment.transfer.clone(),
+                icon_layout: TextLayout::from_text(file_icon(&attachment.mime_type).to_string()),
+                name_layout: TextLayout::from_text(attachment.file_name.clone()),
+                status_layout: TextLayout::from_text(status_text(attachment)),
+                save_layout: TextLayout::from_text(SAVE_TEXT.to_string()),
+                open_layout: TextLayout::from_text(OPEN_TEXT.to_string()),
+                rect: Rect::ZERO,
+                save_rect: Rect::ZERO,
+                open_rect: Rect::ZERO,
+            })
+            .collect();
+        self.apply_settings(settings);
+    }
+
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        for card in self.cards.iter_mut() {
+            card.icon_layout.set_text_size(ICON_SIZE);
+            card.name_layout.set_font(FontDescriptor::default().with_weight(FontWeight::SEMI_BOLD).with_size(settings.content_font_size));
+            card.name_layout.set_text_color(settings.theme.text_color.to_druid_color());
+            card.status_layout.set_text_size(settings.datetime_font_size);
+            let status_color = match card.transfer {
+                TransferState::Failed(_) => ERROR_COLOR,
+                _ => settings.datetime_color.to_druid_color(),
+            };
+            card.status_layout.set_text_color(status_color);
+            // Dimmed while they can't be used
+            let action_color = if card.is_busy() {
+                settings.datetime_color.to_druid_color()
+            } else {
+                settings.theme.accent_color.to_druid_color()
+            };
+            for layout in [&mut card.save_layout, &mut card.open_layout] {
+                layout.set_text_size(settings.datetime_font_size);
+                layout.set_text_color(action_color.clone());
+            }
+        }
+    }
+
+    fn action_at(&self, pos: Point) -> Option<(u32, Selector<(u32, u32)>)> {
+        self.cards.iter()
+            .find_map(|card| card.action_at(pos).map(|action| (card.attachment_id, action)))
+    }
+}
+
+impl Widget<WithSettings<Message>> for FileCardsWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, _env: &Env) {
+        match event {
+            Event::MouseMove(mouse) => {
+                if self.action_at(mouse.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+            },
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                if let Some((attachment_id, action)) = self.action_at(mouse.pos) {
+                    ctx.submit_command(action.with((data.1.id, attachment_id)));
+                    ctx.set_handled();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild_cards(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        // The transfer state is part of the attachments, so progress rebuilds the cards too
+        if !old_data.1.attachments.same(&data.1.attachments) {
+            self.rebuild_cards(data);
+            ctx.request_layout();
+        } else if !old_data.0.same(&data.0) {
+            self.apply_settings(&data.0);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &WithSettings<Message>, env: &Env) -> Size {
+        let card_width = bc.max().width.min(CARD_MAX_WIDTH);
+        let mut y = 0.0;
+        for (index, card) in self.cards.iter_mut().enumerate() {
+            if index > 0 {
+                y += CARD_SPACING;
+            }
+            card.icon_layout.rebuild_if_needed(ctx.text(), env);
+            let icon_size = card.icon_layout.size();
+            let text_x = CARD_PADDING + icon_size.width + ICON_SPACING;
+            let text_width = (card_width - text_x - CARD_PADDING).max(0.0);
+            // The name stays on one line and is cut off at the edge in paint. Errors wrap.
+            card.name_layout.set_wrap_width(f64::INFINITY);
+            card.name_layout.rebuild_if_needed(ctx.text(), env);
+            card.status_layout.set_wrap_width(text_width);
+            card.status_layout.rebuild_if_needed(ctx.text(), env);
+            card.save_layout.rebuild_if_needed(ctx.text(), env);
+            card.open_layout.rebuild_if_needed(ctx.text(), env);
+
+            let status_y = y + CARD_PADDING + card.name_layout.size().height + LINE_SPACING;
+            let actions_y = status_y + card.status_layout.size().height + LINE_SPACING;
+            card.save_rect = Rect::from_origin_size(Point::new(text_x, actions_y), card.save_layout.size());
+            card.open_rect = Rect::from_origin_size(
+                Point::new(card.save_rect.x1 + ACTION_SPACING, actions_y),
+                card.open_layout.size()
+            );
+            let mut content_bottom = card.save_rect.y1.max(y + CARD_PADDING + icon_size.height);
+            if let TransferState::Downloading { .. } = card.transfer {
+                content_bottom += LINE_SPACING + PROGRESS_BAR_HEIGHT;
+            }
+            card.rect = Rect::new(0.0, y, card_width, content_bottom + CARD_PADDING);
+            y = card.rect.y1;
+        }
+        let width = if self.cards.is_empty() { 0.0 } else { card_width };
+        bc.constrain(Size::new(width, y))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, _env: &Env) {
+        let settings = &data.0;
+        for card in self.cards.iter() {
+            // Inset so the border isn't cut off
+            let card_shape = RoundedRect::from_rect(card.rect.inset(-CARD_BORDER_WIDTH / 2.0), CARD_RADIUS);
+            ctx.fill(card_shape, &settings.theme.input_background_color.to_druid_color());
+            ctx.stroke(card_shape, &settings.theme.border_color.to_druid_color(), CARD_BORDER_WIDTH);
+
+            let icon_origin = card.rect.origin() + (CARD_PADDING, CARD_PADDING);
+            card.icon_layout.draw(ctx, icon_origin);
+            let text_x = card.save_rect.x0;
+            let name_origin = Point::new(text_x, card.rect.y0 + CARD_PADDING);
+            let status_origin = Point::new(text_x, name_origin.y + card.name_layout.size().height + LINE_SPACING);
+            ctx.with_save(|ctx| {
+                ctx.clip(Rect::new(text_x, card.rect.y0, card.rect.x1 - CARD_PADDING, card.rect.y1));
+                card.name_layout.draw(ctx, name_origin);
+            });
+            card.status_layout.draw(ctx, status_origin);
+            card.save_layout.draw(ctx, card.save_rect.origin());
+            card.open_layout.draw(ctx, card.open_rect.origin());
+
+            if let TransferState::Downloading { progress, .. } = card.transfer {
+                let bar_y = card.rect.y1 - CARD_PADDING - PROGRESS_BAR_HEIGHT;
+                let bar_rect = Rect::new(text_x, bar_y, card.rect.x1 - CARD_PADDING, bar_y + PROGRESS_BAR_HEIGHT);
+                ctx.fill(bar_rect, &settings.theme.border_color.to_druid_color());
+                let done_width = bar_rect.width() * progress.clamp(0.0, 1.0);
+                ctx.fill(bar_rect.with_size((done_width, PROGRESS_BAR_HEIGHT)), &settings.theme.accent_color.to_druid_color());
+            }
+        }
+    }
+}
+
+/// The size, or what's going on with the file
+fn status_text(attachment: &Attachment) -> String {
+    match &attachment.transfer {
+        TransferState::Idle | TransferState::ChoosingSaveLocation => helper_functions::format_file_size(attachment.size_in_bytes),
+        TransferState::Downloading { progress, .. } => format!(
+            "Downloading... {}% of {}",
+            (progress.clamp(0.0, 1.0) * 100.0).round(),
+            helper_functions::format_file_size(attachment.size_in_bytes)
+        ),
+        TransferState::Failed(error) => format!("Failed: {}", error),
+    }
+}
+
+fn file_icon(mime_type: &str) -> &'static str {
+    let (kind, subtype) = mime_type.split_once('/').unwrap_or((mime_type, ""));
+    match (kind, subtype) {
+        ("audio", _) => "🎵",
+        ("video", _) => "🎬",
+        ("image", _) => "🖼",
+        ("text", _) => "📝",
+        (_, "pdf") => "📕",
+        (_, "zip" | "gzip" | "x-7z-compressed" | "vnd.rar" | "x-tar") => "🗜",
+        _ => "📄",
+    }
+}
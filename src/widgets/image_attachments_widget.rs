@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use druid::kurbo::{Rect, RoundedRect};
+use druid::piet::{Image as _, InterpolationMode, PietImage};
+use druid::widget::prelude::*;
+use druid::{Cursor, ImageBuf, MouseButton, Point};
+use crate::Message;
+use crate::data::attachment_data::Attachment;
+use crate::helper::layout_settings::WithSettings;
+use crate::image_viewer_ui;
+
+/// Images taller than this are scaled down, even when they would fit the width
+const MAX_IMAGE_HEIGHT: f64 = 300.0;
+const IMAGE_SPACING: f64 = 4.0;
+const IMAGE_RADIUS: f64 = 4.0;
+
+struct InlineImage {
+    attachment: Attachment,
+    source: Arc<ImageBuf>,
+    /// Made from the source on the first paint, since that needs the render context
+    piet_image: Option<PietImage>,
+    rect: Rect,
+}
+
+/// The image attachments of a message, stacked under its text and scaled to fit the content width.
+/// Clicking one opens it in the viewer. Takes no space when there are none.
+pub struct ImageAttachmentsWidget {
+    images: Vec<InlineImage>,
+    /// The index of the image the mouse went down on
+    pressed_image: Option<usize>,
+}
+
+impl ImageAttachmentsWidget {
+    pub fn new() -> Self {
+        ImageAttachmentsWidget { images: Vec::new(), pressed_image: None }
+    }
+
+    /// Only needed when the attachments change
+    fn rebuild_images(&mut self, message: &Message) {
+        self.images = message.attachments.iter().filter_map(|attachment| {
+            attachment.image.clone().map(|source| InlineImage {
+                attachment: attachment.clone(),
+                source,
+                piet_image: None,
+                rect: Rect::ZERO,
+            })
+        }).collect();
+        self.pressed_image = None;
+    }
+
+    fn image_at(&self, pos: Point) -> Option<usize> {
+        self.images.iter().position(|image| image.rect.contains(pos))
+    }
+}
+
+impl Widget<WithSettings<Message>> for ImageAttachmentsWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut WithSettings<Message>, _env: &Env) {
+        match event {
+            Event::MouseMove(mouse) => {
+                if self.image_at(mouse.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+            },
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(image_index) = self.image_at(mouse.pos) {
+                    self.pressed_image = Some(image_index);
+                    ctx.set_active(true);
+                    ctx.set_handled();
+                }
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(pressed_image) = self.pressed_image.take() {
+                    ctx.set_active(false);
+                    if self.image_at(mouse.pos) == Some(pressed_image) {
+                        image_viewer_ui::open_image_viewer(ctx, &self.images[pressed_image].attachment);
+                    }
+                    ctx.set_handled();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild_images(&data.1);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        if !old_data.1.attachments.same(&data.1.attachments) {
+            self.rebuild_images(&data.1);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &WithSettings<Message>, _env: &Env) -> Size {
+        // The width is the content width from the settings, passed down by the message
+        let width_available = bc.max().width;
+        let mut y = 0.0;
+        let mut widest_image: f64 = 0.0;
+        for (index, image) in self.images.iter_mut().enumerate() {
+            if index > 0 {
+                y += IMAGE_SPACING;
+            }
+            let source_size = Size::new(image.source.width() as f64, image.source.height() as f64);
+            // Never scaled up, only down to fit
+            let scale = (width_available / source_size.width)
+                .min(MAX_IMAGE_HEIGHT / source_size.height)
+                .min(1.0);
+            let image_size = Size::new(source_size.width * scale, source_size.height * scale);
+            image.rect = Rect::from_origin_size(Point::new(0.0, y), image_size);
+            widest_image = widest_image.max(image_size.width);
+            y += image_size.height;
+        }
+        bc.constrain(Size::new(widest_image, y))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &WithSettings<Message>, _env: &Env) {
+        for image in self.images.iter_mut() {
+            if image.piet_image.is_none() {
+                image.piet_image = Some(image.source.to_image(ctx.render_ctx));
+            }
+            if let Some(piet_image) = image.piet_image.as_ref() {
+                if piet_image.size().is_empty() {
+                    continue;
+                }
+                let rect = image.rect;
+                ctx.with_save(|ctx| {
+                    ctx.clip(RoundedRect::from_rect(rect, IMAGE_RADIUS));
+                    ctx.draw_image(piet_image, rect, InterpolationMode::Bilinear);
+                });
+            }
+        }
+    }
+}
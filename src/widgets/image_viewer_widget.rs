@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use druid::kurbo::Rect;
+use druid::piet::{InterpolationMode, PietImage};
+use druid::widget::prelude::*;
+use druid::{ImageBuf, KbKey, MouseButton, Point, Vec2};
+use crate::AppState;
+
+/// How much one step of the scroll wheel zooms by
+const ZOOM_STEP: f64 = 1.2;
+/// How far the scroll wheel moves for one step, on most platforms
+const WHEEL_STEP: f64 = 120.0;
+const MIN_ZOOM: f64 = 0.05;
+const MAX_ZOOM: f64 = 20.0;
+
+/// Shows one image at full size. The scroll wheel zooms around the mouse, dragging pans,
+/// and double-clicking or pressing 0 fits the image to the window again.
+pub struct ImageViewerWidget {
+    source: Arc<ImageBuf>,
+    piet_image: Option<PietImage>,
+    /// Logical pixels of the window per image pixel
+    zoom: f64,
+    /// Where the image's top left corner is drawn
+    offset: Vec2,
+    /// The last mouse position while dragging
+    drag_from: Option<Point>,
+    /// Whether the image still follows the window size, which stops once it's zoomed or moved
+    is_fit: bool,
+}
+
+impl ImageViewerWidget {
+    pub fn new(source: Arc<ImageBuf>) -> Self {
+        ImageViewerWidget { source, piet_image: None, zoom: 1.0, offset: Vec2::ZERO, drag_from: None, is_fit: true }
+    }
+
+    fn image_size(&self) -> Size {
+        Size::new(self.source.width() as f64, self.source.height() as f64)
+    }
+
+    /// Scales the image to fit the window, without making small images bigger, and centers it
+    fn fit_to_window(&mut self, window_size: Size) {
+        let image_size = self.image_size();
+        if image_size.is_empty() {
+            return;
+        }
+        let zoom = (window_size.width / image_size.width)
+            .min(window_size.height / image_size.height)
+            .clamp(MIN_ZOOM, 1.0);
+        self.zoom = zoom;
+        self.offset = ((window_size - image_size * zoom) / 2.0).to_vec2();
+        self.is_fit = true;
+    }
+
+    /// Keeps the image point under `anchor` in the same place on screen
+    fn zoom_around(&mut self, anchor: Point, factor: f64) {
+        let new_zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let image_point = (anchor.to_vec2() - self.offset) / self.zoom;
+        self.offset = anchor.to_vec2() - image_point * new_zoom;
+        self.zoom = new_zoom;
+        self.is_fit = false;
+    }
+}
+
+impl Widget<AppState> for ImageViewerWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut AppState, _env: &Env) {
+        match event {
+            Event::WindowConnected => ctx.request_focus(),
+            Event::Wheel(mouse) => {
+                self.zoom_around(mouse.pos, ZOOM_STEP.powf(-mouse.wheel_delta.y / WHEEL_STEP));
+                ctx.request_paint();
+                ctx.set_handled();
+            },
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                if mouse.count == 2 {
+                    self.fit_to_window(ctx.size());
+                    ctx.request_paint();
+                } else {
+                    self.drag_from = Some(mouse.pos);
+                    ctx.set_active(true);
+                }
+                ctx.set_handled();
+            },
+            Event::MouseMove(mouse) => {
+                if let Some(drag_from) = self.drag_from {
+                    self.offset += mouse.pos - drag_from;
+                    self.is_fit = false;
+                    self.drag_from = Some(mouse.pos);
+                    ctx.request_paint();
+                }
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left => {
+                if self.drag_from.take().is_some() {
+                    ctx.set_active(false);
+                }
+            },
+            Event::KeyDown(key) => {
+                let center = ctx.size().to_rect().center();
+                match &key.key {
+                    KbKey::Character(c) if c == "+" || c == "=" => self.zoom_around(center, ZOOM_STEP),
+                    KbKey::Character(c) if c == "-" => self.zoom_around(center, 1.0 / ZOOM_STEP),
+                    KbKey::Character(c) if c == "0" => self.fit_to_window(ctx.size()),
+                    _ => return,
+                }
+                ctx.request_paint();
+                ctx.set_handled();
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, _data: &AppState, _env: &Env) {
+        if let LifeCycle::BuildFocusChain = event {
+            ctx.register_for_focus();
+        }
+    }
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, _old_data: &AppState, _data: &AppState, _env: &Env) {}
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &AppState, _env: &Env) -> Size {
+        let size = bc.max();
+        if self.is_fit && size.is_finite() {
+            self.fit_to_window(size);
+        }
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        let size = ctx.size();
+        ctx.fill(size.to_rect(), &data.layout_settings.theme.panel_background_color.to_druid_color());
+        let zoom = self.zoom;
+        if self.piet_image.is_none() {
+            self.piet_image = Some(self.source.to_image(ctx.render_ctx));
+        }
+        if let Some(piet_image) = self.piet_image.as_ref() {
+            let image_rect = Rect::from_origin_size(self.offset.to_point(), self.image_size() * zoom);
+            // Pixels stay sharp once zoomed in past full size
+            let interpolation = if zoom > 1.0 { InterpolationMode::NearestNeighbor } else { InterpolationMode::Bilinear };
+            ctx.with_save(|ctx| {
+                ctx.clip(size.to_rect());
+                ctx.draw_image(piet_image, image_rect, interpolation);
+            });
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use druid::kurbo::{Rect, RoundedRect};
+use druid::piet::{TextLayout as _, TextStorage as _};
+use druid::text::{RichText, TextLayout};
+use druid::widget::prelude::*;
+use druid::text::RichTextBuilder;
+use druid::{Application, Cursor, FontDescriptor, FontFamily, FontStyle, HotKey, MouseButton, Point, SysMods};
+use crate::Message;
+use crate::LayoutSettings;
+use crate::{link_ui, message_ui};
+use crate::helper::layout_settings::WithSettings;
+use crate::helper::message_formatting::{self, FormattedBlock, FormattedText, LinkSpan};
+
+const BLOCK_SPACING: f64 = 4.0;
+const CODE_BLOCK_PADDING: f64 = 4.0;
+const CODE_BLOCK_RADIUS: f64 = 3.0;
+const QUOTE_BAR_WIDTH: f64 = 3.0;
+/// Space from the left of the quote bar to the quoted text
+const QUOTE_INDENT: f64 = 9.0;
+const DELETED_PLACEHOLDER: &str = "This message was deleted";
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockKind {
+    Paragraph,
+    CodeBlock,
+    Quote,
+}
+
+struct BlockLayout {
+    kind: BlockKind,
+    layout: TextLayout<RichText>,
+    /// The text without the formatting, for copying
+    plain_text: String,
+    links: Vec<LinkSpan>,
+    /// Where the text is drawn
+    text_origin: Point,
+    /// The whole block, including the code background or the quote bar
+    rect: Rect,
+}
+
+/// A spot in the message's text. Ordered by block, then by offset.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TextPosition {
+    block: usize,
+    /// Byte offset in the block's text
+    offset: usize,
+}
+
+/// The formatted content of a message, laid out as a column of paragraphs,
+/// code blocks and quotes. The text can be selected with the mouse and copied.
+pub struct MessageContentWidget {
+    blocks: Vec<BlockLayout>,
+    /// The link the mouse went down on, opened if the mouse also goes up on it
+    pressed_link: Option<String>,
+    /// Where the selection started, and where it was dragged to
+    selection: Option<(TextPosition, TextPosition)>,
+    /// The content font size is multiplied by this, which makes emoji-only messages large
+    text_scale: f64,
+}
+
+impl MessageContentWidget {
+    pub fn new() -> Self {
+        MessageContentWidget { blocks: Vec::new(), pressed_link: None, selection: None, text_scale: 1.0 }
+    }
+
+    /// Parses the message again. Only needed when the text changes.
+    fn rebuild_blocks(&mut self, data: &WithSettings<Message>) {
+        let (settings, message) = data;
+        let mut formatted = if message.deleted {
+            let mut builder = RichTextBuilder::new();
+            builder.push(DELETED_PLACEHOLDER)
+                .style(FontStyle::Italic)
+                .text_color(settings.datetime_color.to_druid_color());
+            vec![FormattedBlock::Paragraph(FormattedText { text: builder.build(), links: Vec::new() })]
+        } else {
+            message_formatting::format_message(message.displayed_text(), &message.mention_names)
+        };
+        if formatted.is_empty() {
+            // Keep the height of one line for empty messages
+            formatted.push(FormattedBlock::Paragraph(FormattedText {
+                text: RichText::new("".into()),
+                links: Vec::new(),
+            }));
+        }
+        self.blocks = formatted.into_iter().map(|block| {
+            let (kind, formatted_text) = match block {
+                FormattedBlock::Paragraph(text) => (BlockKind::Paragraph, text),
+                FormattedBlock::CodeBlock(code) => (BlockKind::CodeBlock, FormattedText {
+                    text: RichText::new(code.as_str().into()),
+                    links: Vec::new(),
+                }),
+                FormattedBlock::Quote(text) => (BlockKind::Quote, text),
+            };
+            let mut layout = TextLayout::from_text(formatted_text.text);
+            if kind == BlockKind::CodeBlock {
+                layout.set_font(FontDescriptor::new(FontFamily::MONOSPACE));
+            }
+            let plain_text = formatted_text.text.as_str().to_string();
+            BlockLayout { kind, layout, plain_text, links: formatted_text.links, text_origin: Point::ORIGIN, rect: Rect::ZERO }
+        }).collect();
+        self.pressed_link = None;
+        self.selection = None;
+        self.apply_settings(settings);
+    }
+
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        for block in self.blocks.iter_mut() {
+            block.layout.set_text_size(settings.content_font_size * self.text_scale);
+        }
+    }
+
+    /// Only needed when the scale changes
+    pub fn set_text_scale(&mut self, text_scale: f64, settings: &LayoutSettings) {
+        self.text_scale = text_scale;
+        self.apply_settings(settings);
+    }
+
+    /// The URL of the link under the position, if there is one
+    fn link_at(&self, pos: Point) -> Option<&str> {
+        let block = self.blocks.iter().find(|block| block.rect.contains(pos) && !block.links.is_empty())?;
+        let text_layout = block.layout.layout()?;
+        let hit = text_layout.hit_test_point(pos - block.text_origin.to_vec2());
+        if !hit.is_inside {
+            return None;
+        }
+        block.links.iter()
+            .find(|link| link.range.contains(&hit.idx))
+            .map(|link| link.url.as_str())
+    }
+
+    /// The closest spot in the text to the position, even when it's outside of the text
+    fn position_at(&self, pos: Point) -> Option<TextPosition> {
+        let block_index = self.blocks.iter()
+            .position(|block| pos.y < block.rect.y1)
+            .unwrap_or(self.blocks.len().checked_sub(1)?);
+        let block = &self.blocks[block_index];
+        let hit = block.layout.layout()?.hit_test_point(pos - block.text_origin.to_vec2());
+        Some(TextPosition { block: block_index, offset: hit.idx })
+    }
+
+    /// The word, or the single other character, at the position
+    fn word_at(&self, position: TextPosition) -> (TextPosition, TextPosition) {
+        let text = &self.blocks[position.block].plain_text;
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let offset = position.offset.min(text.len());
+        let start = text[..offset].char_indices().rev()
+            .take_while(|(_, c)| is_word_char(*c))
+            .last()
+            .map_or(offset, |(index, _)| index);
+        let mut end = text[offset..].char_indices()
+            .find(|(_, c)| !is_word_char(*c))
+            .map_or(text.len(), |(index, _)| offset + index);
+        if start == end {
+            end += text[offset..].chars().next().map_or(0, char::len_utf8);
+        }
+        (TextPosition { block: position.block, offset: start }, TextPosition { block: position.block, offset: end })
+    }
+
+    fn select_all(&mut self) {
+        if let Some(last) = self.blocks.last() {
+            let end = TextPosition { block: self.blocks.len() - 1, offset: last.plain_text.len() };
+            self.selection = Some((TextPosition { block: 0, offset: 0 }, end));
+        }
+    }
+
+    /// The selection from its first to its last position, if anything is selected
+    fn ordered_selection(&self) -> Option<(TextPosition, TextPosition)> {
+        let (anchor, active) = self.selection?;
+        if anchor == active {
+            return None;
+        }
+        Some((anchor.min(active), anchor.max(active)))
+    }
+
+    /// The part of the block's text that is selected
+    fn selected_range(&self, block_index: usize) -> Option<std::ops::Range<usize>> {
+        let (start, end) = self.ordered_selection()?;
+        if block_index < start.block || block_index > end.block {
+            return None;
+        }
+        let block_len = self.blocks[block_index].plain_text.len();
+        let range_start = if block_index == start.block { start.offset } else { 0 };
+        let range_end = if block_index == end.block { end.offset } else { block_len };
+        Some(range_start.min(block_len)..range_end.min(block_len))
+    }
+
+    /// The selected text, with the blocks on separate lines
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.ordered_selection()?;
+        let parts: Vec<&str> = (start.block..=end.block)
+            .filter_map(|block_index| {
+                let range = self.selected_range(block_index)?;
+                self.blocks[block_index].plain_text.get(range)
+            })
+            .collect();
+        Some(parts.join("\n"))
+    }
+}
+
+impl Widget<WithSettings<Message>> for MessageContentWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, _env: &Env) {
+        match event {
+            Event::MouseMove(mouse) => {
+                if ctx.is_active() && self.pressed_link.is_none() {
+                    // Dragging a selection
+                    if let (Some((anchor, _)), Some(position)) = (self.selection, self.position_at(mouse.pos)) {
+                        self.selection = Some((anchor, position));
+                        ctx.request_paint();
+                    }
+                }
+                if self.link_at(mouse.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.set_cursor(&Cursor::IBeam);
+                }
+            },
+            Event::MouseDown(mouse) => {
+                let link = self.link_at(mouse.pos).map(str::to_string);
+                match (mouse.button, link) {
+                    (MouseButton::Left, Some(link)) => {
+                        self.pressed_link = Some(link);
+                        ctx.set_active(true);
+                        ctx.set_handled();
+                    },
+                    (MouseButton::Right, Some(link)) => {
+                        link_ui::show_link_menu(ctx, &link, mouse.window_pos);
+                        ctx.set_handled();
+                    },
+                    (MouseButton::Left, None) => {
+                        if let Some(position) = self.position_at(mouse.pos) {
+                            match mouse.count {
+                                1 => self.selection = Some((position, position)),
+                                2 => self.selection = Some(self.word_at(position)),
+                                _ => self.select_all(),
+                            }
+                            ctx.request_focus();
+                            ctx.set_active(true);
+                            ctx.request_paint();
+                            ctx.set_handled();
+                        }
+                    },
+                    (MouseButton::Right, None) => {
+                        message_ui::show_message_menu(ctx, &data.1, self.selected_text(), mouse.window_pos);
+                        ctx.set_handled();
+                    },
+                    _ => {},
+                }
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(pressed_link) = self.pressed_link.take() {
+                    if self.link_at(mouse.pos) == Some(pressed_link.as_str()) {
+                        link_ui::open_link(ctx, &pressed_link);
+                    }
+                    ctx.set_handled();
+                }
+                ctx.set_active(false);
+            },
+            Event::KeyDown(key) if HotKey::new(SysMods::Cmd, "c").matches(key) => {
+                if let Some(selected_text) = self.selected_text() {
+                    Application::global().clipboard().put_string(selected_text);
+                    ctx.set_handled();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        match event {
+            LifeCycle::WidgetAdded => self.rebuild_blocks(data),
+            // Only one message has a selection at a time
+            LifeCycle::FocusChanged(false) => {
+                self.selection = None;
+                ctx.request_paint();
+            },
+            _ => {},
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        let (old_message, message) = (&old_data.1, &data.1);
+        if old_message.displayed_text() != message.displayed_text() || old_message.deleted != message.deleted || !old_message.mention_names.same(&message.mention_names) {
+            self.rebuild_blocks(data);
+            ctx.request_layout();
+        } else if !old_data.0.same(&data.0) {
+            if data.1.deleted {
+                // The placeholder's color comes from the settings
+                self.rebuild_blocks(data);
+            } else {
+                self.apply_settings(&data.0);
+            }
+            ctx.request_layout();
+        }
+        if self.blocks.iter().any(|block| block.layout.needs_rebuild_after_update(ctx)) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &WithSettings<Message>, env: &Env) -> Size {
+        let width_available = bc.max().width;
+        let mut y = 0.0;
+        let mut widest_block: f64 = 0.0;
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if index > 0 {
+                y += BLOCK_SPACING;
+            }
+            let (text_x, inset) = match block.kind {
+                BlockKind::Paragraph => (0.0, 0.0),
+                BlockKind::CodeBlock => (CODE_BLOCK_PADDING, CODE_BLOCK_PADDING),
+                BlockKind::Quote => (QUOTE_INDENT, 0.0),
+            };
+            block.layout.set_wrap_width((width_available - text_x - inset).max(0.0));
+            block.layout.rebuild_if_needed(ctx.text(), env);
+            let text_size = block.layout.size();
+            block.text_origin = Point::new(text_x, y + inset);
+            block.rect = Rect::new(0.0, y, text_x + text_size.width + inset, y + text_size.height + inset * 2.0);
+            widest_block = widest_block.max(block.rect.width());
+            y = block.rect.y1;
+        }
+        bc.constrain(Size::new(widest_block, y))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, env: &Env) {
+        let settings = &data.0;
+        for (block_index, block) in self.blocks.iter().enumerate() {
+            match block.kind {
+                BlockKind::CodeBlock => {
+                    ctx.fill(
+                        RoundedRect::from_rect(block.rect, CODE_BLOCK_RADIUS),
+                        &settings.theme.input_background_color.to_druid_color()
+                    );
+                },
+                BlockKind::Quote => {
+                    let bar = Rect::new(block.rect.x0, block.rect.y0, block.rect.x0 + QUOTE_BAR_WIDTH, block.rect.y1);
+                    ctx.fill(bar, &settings.theme.accent_color.to_druid_color());
+                },
+                BlockKind::Paragraph => {},
+            }
+            if let (Some(range), Some(text_layout)) = (self.selected_range(block_index), block.layout.layout()) {
+                for selection_rect in text_layout.rects_for_range(range) {
+                    ctx.fill(selection_rect + block.text_origin.to_vec2(), &env.get(druid::theme::SELECTED_TEXT_BACKGROUND_COLOR));
+                }
+            }
+            block.layout.draw(ctx, block.text_origin);
+        }
+    }
+}
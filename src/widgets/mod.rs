@@ -1,3 +1,14 @@
 pub mod timeline_item_widget;
 pub mod single_message_widget;
-pub mod chat_window_widget;
\ No newline at end of file
+pub mod message_content_widget;
+pub mod file_cards_widget;
+pub mod image_attachments_widget;
+pub mod image_viewer_widget;
+pub mod reactions_widget;
+pub mod edited_marker_widget;
+pub mod emoji_grid_widget;
+pub mod reply_preview_widget;
+pub mod typing_indicator_widget;
+pub mod chat_window_widget;
+pub mod color_picker_widget;
+pub mod avatar_widget;
\ No newline at end of file
@@ -0,0 +1,161 @@
+use druid::kurbo::{Rect, RoundedRect};
+use druid::text::TextLayout;
+use druid::widget::prelude::*;
+use druid::{Cursor, MouseButton, Point, Vec2};
+use crate::Message;
+use crate::LayoutSettings;
+use crate::helper::layout_settings::WithSettings;
+use crate::core_interface::CORE_SUPPORT;
+use crate::message_ui::TOGGLE_REACTION;
+
+/// Chip text is this fraction of the message text size
+const CHIP_TEXT_SIZE_RATIO: f64 = 0.85;
+const CHIP_PADDING_X: f64 = 6.0;
+const CHIP_PADDING_Y: f64 = 2.0;
+const CHIP_SPACING: f64 = 4.0;
+const CHIP_BORDER_WIDTH: f64 = 1.0;
+/// How strongly the accent color shows behind chips we reacted with
+const SELF_REACTED_ALPHA: f64 = 0.25;
+
+struct ReactionChip {
+    emoji: String,
+    reacted_by_self: bool,
+    layout: TextLayout<String>,
+    rect: Rect,
+}
+
+/// The reactions on a message as a wrapping row of chips with the emoji and count.
+/// Clicking a chip toggles our own reaction with that emoji.
+pub struct ReactionsWidget {
+    chips: Vec<ReactionChip>,
+    /// The index of the chip the mouse went down on
+    pressed_chip: Option<usize>,
+}
+
+impl ReactionsWidget {
+    pub fn new() -> Self {
+        ReactionsWidget { chips: Vec::new(), pressed_chip: None }
+    }
+
+    /// Only needed when the reactions change
+    fn rebuild_chips(&mut self, data: &WithSettings<Message>) {
+        let (settings, message) = data;
+        self.chips = message.reactions.iter().map(|reaction| {
+            ReactionChip {
+                emoji: reaction.emoji.clone(),
+                reacted_by_self: reaction.reacted_by_self,
+                layout: TextLayout::from_text(format!("{} {}", reaction.emoji, reaction.count)),
+                rect: Rect::ZERO,
+            }
+        }).collect();
+        self.pressed_chip = None;
+        self.apply_settings(settings);
+    }
+
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        for chip in self.chips.iter_mut() {
+            chip.layout.set_text_size(settings.content_font_size * CHIP_TEXT_SIZE_RATIO);
+            chip.layout.set_text_color(settings.theme.text_color.to_druid_color());
+        }
+    }
+
+    fn chip_at(&self, pos: Point) -> Option<usize> {
+        self.chips.iter().position(|chip| chip.rect.contains(pos))
+    }
+}
+
+impl Widget<WithSettings<Message>> for ReactionsWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, _env: &Env) {
+        if !CORE_SUPPORT.reactions {
+            // The chips only show the reactions until the core can change them
+            return;
+        }
+        match event {
+            Event::MouseMove(mouse) => {
+                if self.chip_at(mouse.pos).is_some() {
+                    ctx.set_cursor(&Cursor::Pointer);
+                } else {
+                    ctx.clear_cursor();
+                }
+            },
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(chip_index) = self.chip_at(mouse.pos) {
+                    self.pressed_chip = Some(chip_index);
+                    ctx.set_active(true);
+                    ctx.set_handled();
+                }
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left => {
+                if let Some(pressed_chip) = self.pressed_chip.take() {
+                    ctx.set_active(false);
+                    if self.chip_at(mouse.pos) == Some(pressed_chip) {
+                        // The core reports the new reactions back once it's done
+                        let emoji = self.chips[pressed_chip].emoji.clone();
+                        ctx.submit_command(TOGGLE_REACTION.with((data.1.id, emoji)));
+                    }
+                    ctx.set_handled();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild_chips(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        if !old_data.1.reactions.same(&data.1.reactions) {
+            self.rebuild_chips(data);
+            ctx.request_layout();
+        } else if !old_data.0.same(&data.0) {
+            self.apply_settings(&data.0);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &WithSettings<Message>, env: &Env) -> Size {
+        let width_available = bc.max().width;
+        let mut x = 0.0;
+        let mut y = 0.0;
+        let mut row_height: f64 = 0.0;
+        let mut widest_row: f64 = 0.0;
+        for chip in self.chips.iter_mut() {
+            chip.layout.rebuild_if_needed(ctx.text(), env);
+            let chip_size = chip.layout.size() + Size::new(CHIP_PADDING_X * 2.0, CHIP_PADDING_Y * 2.0);
+            // Wrap to the next row, unless it's the first chip on this one
+            if x > 0.0 && x + chip_size.width > width_available {
+                x = 0.0;
+                y += row_height + CHIP_SPACING;
+                row_height = 0.0;
+            }
+            chip.rect = Rect::from_origin_size(Point::new(x, y), chip_size);
+            x += chip_size.width + CHIP_SPACING;
+            row_height = row_height.max(chip_size.height);
+            widest_row = widest_row.max(chip.rect.x1);
+        }
+        bc.constrain(Size::new(widest_row, y + row_height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, _env: &Env) {
+        let settings = &data.0;
+        for chip in self.chips.iter() {
+            // Pill shaped, inset so the border isn't cut off
+            let chip_shape = RoundedRect::from_rect(
+                chip.rect.inset(-CHIP_BORDER_WIDTH / 2.0),
+                chip.rect.height() / 2.0
+            );
+            if chip.reacted_by_self {
+                let accent_color = settings.theme.accent_color.to_druid_color();
+                ctx.fill(chip_shape, &accent_color.clone().with_alpha(SELF_REACTED_ALPHA));
+                ctx.stroke(chip_shape, &accent_color, CHIP_BORDER_WIDTH);
+            } else {
+                ctx.fill(chip_shape, &settings.theme.input_background_color.to_druid_color());
+                ctx.stroke(chip_shape, &settings.theme.border_color.to_druid_color(), CHIP_BORDER_WIDTH);
+            }
+            chip.layout.draw(ctx, chip.rect.origin() + Vec2::new(CHIP_PADDING_X, CHIP_PADDING_Y));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use druid::kurbo::Rect;
+use druid::text::{RichText, RichTextBuilder, TextLayout};
+use druid::widget::prelude::*;
+use druid::{Cursor, FontWeight, MouseButton, Point};
+use crate::Message;
+use crate::LayoutSettings;
+use crate::helper::layout_settings::WithSettings;
+use crate::message_ui::SCROLL_TO_MESSAGE;
+
+/// Quoted text is this fraction of the message text size
+const PREVIEW_TEXT_SIZE_RATIO: f64 = 0.85;
+const PREVIEW_BAR_WIDTH: f64 = 2.0;
+/// Space from the left of the bar to the text
+const PREVIEW_INDENT: f64 = 6.0;
+
+/// A one line preview of the message being replied to, with its sender.
+/// Takes no space when the message isn't a reply. Clicking it goes to the original.
+pub struct ReplyPreviewWidget {
+    layout: Option<TextLayout<RichText>>,
+}
+
+impl ReplyPreviewWidget {
+    pub fn new() -> Self {
+        ReplyPreviewWidget { layout: None }
+    }
+
+    /// Only needed when the reply or the settings change
+    fn rebuild_layout(&mut self, data: &WithSettings<Message>) {
+        let (settings, message) = data;
+        self.layout = message.reply_to.as_ref().map(|reply| {
+            let mut builder = RichTextBuilder::new();
+            builder.push(&reply.sender_name)
+                .weight(FontWeight::BOLD)
+                .text_color(settings.theme.accent_color.to_druid_color());
+            builder.push(" ");
+            builder.push(&reply.first_line);
+            TextLayout::from_text(builder.build())
+        });
+        self.apply_settings(settings);
+    }
+
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        if let Some(layout) = self.layout.as_mut() {
+            layout.set_text_size(settings.content_font_size * PREVIEW_TEXT_SIZE_RATIO);
+            layout.set_text_color(settings.datetime_color.to_druid_color());
+        }
+    }
+}
+
+impl Widget<WithSettings<Message>> for ReplyPreviewWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, _env: &Env) {
+        let reply = match &data.1.reply_to {
+            Some(reply) => reply,
+            None => return,
+        };
+        match event {
+            Event::MouseMove(_) => ctx.set_cursor(&Cursor::Pointer),
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                ctx.set_active(true);
+                ctx.set_handled();
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left && ctx.is_active() => {
+                ctx.set_active(false);
+                if ctx.is_hot() {
+                    ctx.submit_command(SCROLL_TO_MESSAGE.with(reply.message_id));
+                }
+                ctx.set_handled();
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &WithSettings<Message>, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild_layout(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, _env: &Env) {
+        // The sender's color comes from the theme, so settings changes rebuild it too
+        if !old_data.1.reply_to.same(&data.1.reply_to) || !old_data.0.same(&data.0) {
+            self.rebuild_layout(data);
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &WithSettings<Message>, env: &Env) -> Size {
+        match self.layout.as_mut() {
+            Some(layout) => {
+                // Kept to one line, and cut off at the edge in paint
+                layout.set_wrap_width(f64::INFINITY);
+                layout.rebuild_if_needed(ctx.text(), env);
+                let text_size = layout.size();
+                bc.constrain(Size::new(PREVIEW_INDENT + text_size.width, text_size.height))
+            },
+            None => bc.constrain(Size::ZERO),
+        }
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, _env: &Env) {
+        if let Some(layout) = self.layout.as_ref() {
+            let size = ctx.size();
+            ctx.fill(Rect::new(0.0, 0.0, PREVIEW_BAR_WIDTH, size.height), &data.0.theme.accent_color.to_druid_color());
+            ctx.with_save(|ctx| {
+                ctx.clip(size.to_rect());
+                layout.draw(ctx, Point::new(PREVIEW_INDENT, 0.0));
+            });
+        }
+    }
+}
@@ -1,62 +1,132 @@
-use druid::kurbo::Rect;
-use druid::{Widget, widget, WidgetPod};
+use std::sync::Arc;
+use std::time::Duration;
+
+use druid::kurbo::{Circle, Rect, RoundedRect};
+use druid::{Widget, widget, WidgetExt, WidgetPod, MouseButton, TimerToken, HotKey, KbKey, Selector, SysMods, lens};
 use druid::widget::prelude::*;
 use druid::Point;
 use crate::{Message};
-use druid::piet::Color;
 use crate::LayoutSettings;
-use crate::helper::helper_functions::{self, TimestampFormat};
+use crate::message_ui;
+use crate::helper::layout_settings::WithSettings;
+use crate::helper::helper_functions;
+use super::message_content_widget::MessageContentWidget;
+use super::file_cards_widget::FileCardsWidget;
+use super::image_attachments_widget::ImageAttachmentsWidget;
+use super::reactions_widget::ReactionsWidget;
+use super::edited_marker_widget::EditedMarkerWidget;
+use super::reply_preview_widget::ReplyPreviewWidget;
+
+/// The button that opens the message menu, shown over the top right of the message when hovered
+const MENU_BUTTON_SIZE: f64 = 16.0;
+const MENU_BUTTON_RADIUS: f64 = 3.0;
+const MENU_BUTTON_DOT_RADIUS: f64 = 1.2;
+const MENU_BUTTON_DOT_SPACING: f64 = 4.0;
+/// Space between the message content and its reactions
+const REACTIONS_TOP_MARGIN: f64 = 4.0;
+/// Space above the images and above the file cards, when the message has them
+const ATTACHMENTS_TOP_MARGIN: f64 = 4.0;
+/// Space between the reply preview and the message content
+const REPLY_PREVIEW_BOTTOM_MARGIN: f64 = 3.0;
+/// How long a message stays highlighted after going to it from a reply
+const JUMP_HIGHLIGHT_DURATION: Duration = Duration::from_millis(1500);
+const JUMP_HIGHLIGHT_ALPHA: f64 = 0.3;
+/// Messages that mention us have a faint background in the accent color
+const MENTION_HIGHLIGHT_ALPHA: f64 = 0.12;
+const INLINE_EDITOR_SPACING: f64 = 4.0;
+/// How much larger the text of emoji-only messages is than the content font size
+const LARGE_EMOJI_SCALE: f64 = 2.5;
+
+/// Sent when the inline editor opens, so the editor of that message takes the focus
+const FOCUS_INLINE_EDITOR: Selector<u32> = Selector::new("polysoft.druid-demo.focus_inline_editor");
 
 /// A widget that shows a single message
 /// 
-/// It also handles timestamps, the settings menu, reactions, and more.
+/// It also handles timestamps, the message menu, reactions, and more.
 pub struct SingleMessageWidget {
-    msg_content_label: WidgetPod<Message, widget::Label<Message>>,
-    timestamp_label: WidgetPod<Message, widget::Label<Message>>,
+    reply_preview: WidgetPod<WithSettings<Message>, ReplyPreviewWidget>,
+    msg_content_label: WidgetPod<WithSettings<Message>, MessageContentWidget>,
+    /// Shown in place of the content while we edit the message. Only built while it's open,
+    /// so the text boxes of the other messages aren't in the focus chain.
+    inline_editor: Option<WidgetPod<WithSettings<Message>, Box<dyn Widget<WithSettings<Message>>>>>,
+    images: WidgetPod<WithSettings<Message>, ImageAttachmentsWidget>,
+    file_cards: WidgetPod<WithSettings<Message>, FileCardsWidget>,
+    edited_marker: WidgetPod<WithSettings<Message>, EditedMarkerWidget>,
+    reactions: WidgetPod<WithSettings<Message>, ReactionsWidget>,
+    timestamp_label: WidgetPod<WithSettings<Message>, widget::Label<WithSettings<Message>>>,
+    /// Running while the message is highlighted after a reply preview was clicked
+    jump_highlight_timer: TimerToken,
+    /// Whether someone else's message mentions us. Only checked when the message changes.
+    mentions_self: bool,
 }
 
 impl SingleMessageWidget {
     pub fn new() -> Self {
-        let msg_content_label = WidgetPod::new(
-            widget::Label::new(|item: &Message, _env: &_| {
-                item.message.to_string()
-            })
-            .with_line_break_mode(widget::LineBreaking::WordWrap)
-            .with_text_size(crate::CONTENT_FONT_SIZE_KEY)
-        );
+        let msg_content_label = WidgetPod::new(MessageContentWidget::new());
         let timestamp_label = WidgetPod::new(
-            widget::Label::new(|item: &Message, env: &Env| {
-                let time_format: TimestampFormat = num_traits::FromPrimitive::from_u64(
-                    env.get::<u64>(crate::SIDE_TIME_FORMAT_KEY)
-                ).expect("Invalid side time format index");
-
+            widget::Label::new(|(settings, item): &WithSettings<Message>, _env: &_| {
                 helper_functions::timestamp_to_display_msg(
                     item.timestamp_epoch_seconds,
-                    time_format,
+                    settings.side_time_format,
                 )
             })
             .with_line_break_mode(widget::LineBreaking::Overflow)
-            .with_text_size(crate::DATETIME_FONT_SIZE_KEY)
-            .with_text_color(crate::DATETIME_COLOR_KEY)
         );
         
         SingleMessageWidget {
+            reply_preview: WidgetPod::new(ReplyPreviewWidget::new()),
             msg_content_label: msg_content_label,
-            timestamp_label: timestamp_label
+            inline_editor: None,
+            images: WidgetPod::new(ImageAttachmentsWidget::new()),
+            file_cards: WidgetPod::new(FileCardsWidget::new()),
+            edited_marker: WidgetPod::new(EditedMarkerWidget::new()),
+            reactions: WidgetPod::new(ReactionsWidget::new()),
+            timestamp_label: timestamp_label,
+            jump_highlight_timer: TimerToken::INVALID,
+            mentions_self: false,
         }
     }
 }
 
-impl Widget<Message> for SingleMessageWidget {
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Message, env: &Env) {
+impl Widget<WithSettings<Message>> for SingleMessageWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, env: &Env) {
+        let is_editing = data.1.edit_draft.is_some();
         match event {
-            Event::Command(cmd) if cmd.is(crate::REFRESH_UI_SELECTOR) => {
-                ctx.request_layout();
+            Event::MouseDown(mouse) => {
+                if mouse.button == MouseButton::Left && ctx.is_hot() && !data.1.deleted && !is_editing
+                    && self.menu_button_rect().contains(mouse.pos)
+                {
+                    let selected_text = self.msg_content_label.widget().selected_text();
+                    message_ui::show_message_menu(ctx, &data.1, selected_text, mouse.window_pos);
+                    ctx.set_handled();
+                    return;
+                }
+            },
+            Event::Command(cmd) => {
+                if cmd.get(message_ui::SCROLL_TO_MESSAGE) == Some(&data.1.id) {
+                    ctx.scroll_to_view();
+                    self.jump_highlight_timer = ctx.request_timer(JUMP_HIGHLIGHT_DURATION);
+                    ctx.request_paint();
+                    ctx.set_handled();
+                    return;
+                }
+            },
+            Event::Timer(token) if *token == self.jump_highlight_timer => {
+                self.jump_highlight_timer = TimerToken::INVALID;
                 ctx.request_paint();
-            }
-            _ => {}
+                return;
+            },
+            _ => {},
         }
-        self.msg_content_label.event(ctx, event, data, env);
+        self.reply_preview.event(ctx, event, data, env);
+        match self.inline_editor.as_mut() {
+            Some(inline_editor) if is_editing => inline_editor.event(ctx, event, data, env),
+            _ => self.msg_content_label.event(ctx, event, data, env),
+        }
+        self.images.event(ctx, event, data, env);
+        self.file_cards.event(ctx, event, data, env);
+        self.edited_marker.event(ctx, event, data, env);
+        self.reactions.event(ctx, event, data, env);
         self.timestamp_label.event(ctx, event, data, env);
     }
 
@@ -64,22 +134,73 @@ impl Widget<Message> for SingleMessageWidget {
         &mut self,
         ctx: &mut LifeCycleCtx,
         event: &LifeCycle,
-        data: &Message,
+        data: &WithSettings<Message>,
         env: &Env,
     ) {
         match event {
+            LifeCycle::WidgetAdded => {
+                self.apply_settings(&data.0);
+                self.msg_content_label.widget_mut().set_text_scale(content_text_scale(&data.1), &data.0);
+                self.mentions_self = data.1.mentions_self();
+                if data.1.edit_draft.is_some() {
+                    self.inline_editor = Some(WidgetPod::new(build_inline_editor().boxed()));
+                }
+            },
             LifeCycle::HotChanged(_) => {
                 ctx.request_layout();
                 ctx.request_paint();
             },
             _ => {}
         }
+        self.reply_preview.lifecycle(ctx, event, data, env);
         self.msg_content_label.lifecycle(ctx, event, data, env);
+        if let Some(inline_editor) = self.inline_editor.as_mut() {
+            inline_editor.lifecycle(ctx, event, data, env);
+        }
+        self.images.lifecycle(ctx, event, data, env);
+        self.file_cards.lifecycle(ctx, event, data, env);
+        self.edited_marker.lifecycle(ctx, event, data, env);
+        self.reactions.lifecycle(ctx, event, data, env);
         self.timestamp_label.lifecycle(ctx, event, data, env);
     }
 
-    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &Message, data: &Message, env: &Env) {
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<Message>, data: &WithSettings<Message>, env: &Env) {
+        if !old_data.0.same(&data.0) {
+            self.apply_settings(&data.0);
+            ctx.request_layout();
+        }
+        // The editor is added when it opens and dropped when it closes. A new one gets its first
+        // data when it's added, so it's only updated while it stays open.
+        let was_editing = self.inline_editor.is_some();
+        if was_editing != data.1.edit_draft.is_some() {
+            if was_editing {
+                self.inline_editor = None;
+            } else {
+                self.inline_editor = Some(WidgetPod::new(build_inline_editor().boxed()));
+                ctx.submit_command(FOCUS_INLINE_EDITOR.with(data.1.id));
+            }
+            ctx.children_changed();
+            ctx.request_layout();
+        } else if let Some(inline_editor) = self.inline_editor.as_mut() {
+            inline_editor.update(ctx, data, env);
+        }
+        if !old_data.1.same(&data.1) {
+            if old_data.1.is_emoji_only() != data.1.is_emoji_only() {
+                self.msg_content_label.widget_mut().set_text_scale(content_text_scale(&data.1), &data.0);
+                ctx.request_layout();
+            }
+            let mentions_self = data.1.mentions_self();
+            if mentions_self != self.mentions_self {
+                self.mentions_self = mentions_self;
+                ctx.request_paint();
+            }
+        }
+        self.reply_preview.update(ctx, data, env);
         self.msg_content_label.update(ctx, data, env);
+        self.images.update(ctx, data, env);
+        self.file_cards.update(ctx, data, env);
+        self.edited_marker.update(ctx, data, env);
+        self.reactions.update(ctx, data, env);
         self.timestamp_label.update(ctx, data, env);
     }
 
@@ -87,60 +208,239 @@ impl Widget<Message> for SingleMessageWidget {
         &mut self,
         layout_ctx: &mut LayoutCtx,
         bc: &BoxConstraints,
-        data: &Message,
+        data: &WithSettings<Message>,
         env: &Env,
     ) -> Size {
-        let settings = LayoutSettings::from_env(env);
+        let settings = &data.0;
         // Now position the content label
         let msg_content_bc = helper_functions::to_full_height_area(
             bc.max().width - settings.left_spacing
         );
-        let msg_content_origin = Point::new(settings.left_spacing, 0.0);
-        let msg_size = self.msg_content_label.layout(layout_ctx, &msg_content_bc, data, env);
-        self.msg_content_label.set_origin(layout_ctx, msg_content_origin);
+        // The reply preview goes above the content, inside the bubble
+        let reply_size = self.reply_preview.layout(layout_ctx, &msg_content_bc, data, env);
+        self.reply_preview.set_origin(layout_ctx, Point::new(settings.left_spacing, 0.0));
+        let msg_content_y = if data.1.reply_to.is_some() { reply_size.height + REPLY_PREVIEW_BOTTOM_MARGIN } else { 0.0 };
+        let msg_content_origin = Point::new(settings.left_spacing, msg_content_y);
+        let msg_size = match self.inline_editor.as_mut() {
+            Some(inline_editor) => {
+                let editor_size = inline_editor.layout(layout_ctx, &msg_content_bc, data, env);
+                inline_editor.set_origin(layout_ctx, msg_content_origin);
+                editor_size
+            },
+            None => {
+                let content_size = self.msg_content_label.layout(layout_ctx, &msg_content_bc, data, env);
+                self.msg_content_label.set_origin(layout_ctx, msg_content_origin);
+                content_size
+            },
+        };
+        let msg_content_bottom = msg_content_y + msg_size.height;
+        // The attachments, edited marker and reactions go under the content. Being part of this
+        // widget's size means the bubble and the item's total height grow to fit them.
+        let images_size = self.images.layout(layout_ctx, &msg_content_bc, data, env);
+        let images_y = if images_size.height > 0.0 { msg_content_bottom + ATTACHMENTS_TOP_MARGIN } else { msg_content_bottom };
+        self.images.set_origin(layout_ctx, Point::new(settings.left_spacing, images_y));
+        let images_bottom = images_y + images_size.height;
+        let file_cards_size = self.file_cards.layout(layout_ctx, &msg_content_bc, data, env);
+        let file_cards_y = if file_cards_size.height > 0.0 { images_bottom + ATTACHMENTS_TOP_MARGIN } else { images_bottom };
+        self.file_cards.set_origin(layout_ctx, Point::new(settings.left_spacing, file_cards_y));
+        let attachments_bottom = file_cards_y + file_cards_size.height;
+        let edited_marker_size = self.edited_marker.layout(layout_ctx, &msg_content_bc, data, env);
+        self.edited_marker.set_origin(layout_ctx, Point::new(settings.left_spacing, attachments_bottom));
+        let edited_marker_bottom = attachments_bottom + edited_marker_size.height;
+        let reactions_size = self.reactions.layout(layout_ctx, &msg_content_bc, data, env);
+        let reactions_y = if data.1.reactions.is_empty() { edited_marker_bottom } else { edited_marker_bottom + REACTIONS_TOP_MARGIN };
+        self.reactions.set_origin(layout_ctx, Point::new(settings.left_spacing, reactions_y));
         // Now position the timestamp label
         let timestamp_size = self.timestamp_label.layout(layout_ctx, &bc, data, env);
-        let timestamp_y = msg_size.height - timestamp_size.height;
+        let timestamp_y = msg_content_bottom - timestamp_size.height;
         let mut timestamp_x = 0.0 - timestamp_size.width - settings.left_meta_offset - settings.left_spacing;
         timestamp_x -= settings.bubble_padding;
         let timestamp_origin = Point::new(timestamp_x, timestamp_y);
         // Just using the given bc because we don't want it to wrap.
         self.timestamp_label.set_origin(layout_ctx, timestamp_origin);
-        msg_size
+        Size::new(
+            msg_size.width.max(reactions_size.width).max(reply_size.width).max(edited_marker_size.width)
+                .max(images_size.width).max(file_cards_size.width),
+            reactions_y + reactions_size.height
+        )
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, data: &Message, env: &Env) {
-        let settings = LayoutSettings::from_env(env);
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<Message>, env: &Env) {
+        let settings = &data.0;
+        let is_editing = data.1.edit_draft.is_some();
+        if self.mentions_self {
+            ctx.fill(
+                self.body_rect(is_editing).inflate(1.5, 1.5),
+                &settings.theme.accent_color.to_druid_color().with_alpha(MENTION_HIGHLIGHT_ALPHA)
+            );
+        }
         // Draw hot background (for when user's mouse is hovering over it)
         if ctx.is_hot() {
             ctx.fill(
-                self.msg_content_label.layout_rect().inflate(1.5, 1.5),
-                &Color::rgba8(255, 255, 255, 20)
+                self.body_rect(is_editing).inflate(1.5, 1.5),
+                &settings.theme.hover_highlight_color.to_druid_color()
+            );
+        }
+        if self.jump_highlight_timer != TimerToken::INVALID {
+            ctx.fill(
+                self.body_rect(is_editing).inflate(1.5, 1.5),
+                &settings.theme.accent_color.to_druid_color().with_alpha(JUMP_HIGHLIGHT_ALPHA)
             );
         }
 
-        self.draw_left_line(ctx, &settings);
-        self.msg_content_label.paint(ctx, data, env);
+        self.draw_left_line(ctx, settings, is_editing);
+        self.reply_preview.paint(ctx, data, env);
+        match self.inline_editor.as_mut() {
+            Some(inline_editor) if is_editing => inline_editor.paint(ctx, data, env),
+            _ => self.msg_content_label.paint(ctx, data, env),
+        }
+        self.images.paint(ctx, data, env);
+        self.file_cards.paint(ctx, data, env);
+        self.edited_marker.paint(ctx, data, env);
+        self.reactions.paint(ctx, data, env);
         // Always paint because it's only when hot,
         // and because it's out of bounds.
-        let is_below_profile_pic = self.msg_content_label.layout_rect().height()
+        let is_below_profile_pic = self.content_rect(is_editing).y1
             - self.timestamp_label.layout_rect().height() - settings.picture_size > -10.0;
-        if (data.position_in_group > 0 || settings.left_bubble_flipped || is_below_profile_pic)
+        if (data.1.position_in_group > 0 || settings.left_bubble_flipped || is_below_profile_pic)
             && ctx.is_hot()
         {
             self.timestamp_label.paint_always(ctx, data, env);
         }
+        if ctx.is_hot() && !data.1.deleted && !is_editing {
+            self.draw_menu_button(ctx, settings);
+        }
     }
 }
 
 impl SingleMessageWidget {
+
+    /// Applies the label styling from the settings. Only needed when the settings change.
+    fn apply_settings(&mut self, settings: &LayoutSettings) {
+        self.timestamp_label.widget_mut().set_text_size(settings.datetime_font_size);
+        self.timestamp_label.widget_mut().set_text_color(settings.datetime_color.to_druid_color());
+    }
     
-    fn draw_left_line(&self, ctx: &mut PaintCtx, settings: &LayoutSettings) {
+    /// The content, or the inline editor while it's shown
+    fn content_rect(&self, is_editing: bool) -> Rect {
+        match &self.inline_editor {
+            Some(inline_editor) if is_editing => inline_editor.layout_rect(),
+            _ => self.msg_content_label.layout_rect(),
+        }
+    }
+
+    /// Everything that belongs to the message: the reply preview, the content, the attachments, the edited marker, and the reactions
+    fn body_rect(&self, is_editing: bool) -> Rect {
+        self.reply_preview.layout_rect()
+            .union(self.content_rect(is_editing))
+            .union(self.images.layout_rect())
+            .union(self.file_cards.layout_rect())
+            .union(self.edited_marker.layout_rect())
+            .union(self.reactions.layout_rect())
+    }
+
+    /// Only used when not editing, since the menu is hidden while the editor is open
+    fn menu_button_rect(&self) -> Rect {
+        let body_rect = self.body_rect(false);
+        Rect::new(body_rect.x1 - MENU_BUTTON_SIZE, body_rect.y0, body_rect.x1, body_rect.y0 + MENU_BUTTON_SIZE)
+    }
+
+    /// A small button with three dots
+    fn draw_menu_button(&self, ctx: &mut PaintCtx, settings: &LayoutSettings) {
+        let button_rect = self.menu_button_rect();
+        ctx.fill(
+            RoundedRect::from_rect(button_rect, MENU_BUTTON_RADIUS),
+            &settings.theme.input_background_color.to_druid_color()
+        );
+        let center = button_rect.center();
+        for dot_index in -1..=1 {
+            let dot_center = Point::new(center.x + dot_index as f64 * MENU_BUTTON_DOT_SPACING, center.y);
+            ctx.fill(Circle::new(dot_center, MENU_BUTTON_DOT_RADIUS), &settings.theme.text_color.to_druid_color());
+        }
+    }
+
+    fn draw_left_line(&self, ctx: &mut PaintCtx, settings: &LayoutSettings, is_editing: bool) {
         if settings.show_left_line {
-            let content_label_rect = self.msg_content_label.layout_rect();
+            let content_label_rect = self.body_rect(is_editing);
             let line_x0 = content_label_rect.x0 - settings.left_spacing;
             let line_rect = Rect::new(line_x0, content_label_rect.y0, line_x0 + 1.0, content_label_rect.y1);
-            ctx.fill(line_rect, &Color::GRAY);
+            ctx.fill(line_rect, &settings.theme.left_line_color.to_druid_color());
+        }
+    }
+}
+
+/// How much larger than the content font size the message's text is shown. Emoji-only messages are large.
+fn content_text_scale(message: &Message) -> f64 {
+    if message.is_emoji_only() { LARGE_EMOJI_SCALE } else { 1.0 }
+}
+
+/// A text box with the draft, and buttons to save or cancel the edit
+fn build_inline_editor() -> impl Widget<WithSettings<Message>> {
+    widget::Flex::column()
+        .with_child(
+            widget::TextBox::multiline()
+                .lens(lens::Map::new(
+                    |(_, message): &WithSettings<Message>| message.edit_draft.clone().unwrap_or_default(),
+                    |(_, message): &mut WithSettings<Message>, draft: Arc<String>| {
+                        // Closing the editor while typing shouldn't open it again
+                        if message.edit_draft.is_some() {
+                            message.edit_draft = Some(draft);
+                        }
+                    },
+                ))
+                .controller(InlineEditController)
+                .expand_width()
+        )
+        .with_spacer(INLINE_EDITOR_SPACING)
+        .with_child(
+            widget::Flex::row()
+                .with_child(widget::Button::new("Cancel").on_click(|_ctx: &mut EventCtx, data: &mut WithSettings<Message>, _env: &_| {
+                    data.1.edit_draft = None;
+                }))
+                .with_spacer(INLINE_EDITOR_SPACING)
+                .with_child(widget::Button::new("Save").on_click(|ctx: &mut EventCtx, data: &mut WithSettings<Message>, _env: &_| {
+                    save_edit(ctx, &mut data.1);
+                }))
+        )
+        .cross_axis_alignment(widget::CrossAxisAlignment::End)
+}
+
+/// Sends the draft to the core, and shows it as pending until the core confirms it.
+/// If it fails, or isn't confirmed in time, the editor opens again with the draft.
+/// Saving without changes, or with nothing left, just closes the editor.
+fn save_edit(ctx: &mut EventCtx, message: &mut Message) {
+    if let Some(draft) = message.edit_draft.take() {
+        if !draft.trim().is_empty() && draft.as_str() != message.displayed_text() {
+            message.pending_edit = Some(draft.to_string());
+            ctx.submit_command(message_ui::SAVE_MESSAGE_EDIT.with((message.id, draft.to_string())));
+        }
+    }
+}
+
+/// Focuses the editor when it opens. Escape cancels, and Ctrl+Enter saves.
+struct InlineEditController;
+
+impl<W: Widget<WithSettings<Message>>> widget::Controller<WithSettings<Message>, W> for InlineEditController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<Message>, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.get(FOCUS_INLINE_EDITOR) == Some(&data.1.id) => {
+                ctx.request_focus();
+                ctx.scroll_to_view();
+                ctx.set_handled();
+                return;
+            },
+            Event::KeyDown(key) if key.key == KbKey::Escape => {
+                data.1.edit_draft = None;
+                ctx.set_handled();
+                return;
+            },
+            Event::KeyDown(key) if HotKey::new(SysMods::Cmd, KbKey::Enter).matches(key) => {
+                save_edit(ctx, &mut data.1);
+                ctx.set_handled();
+                return;
+            },
+            _ => {},
         }
+        child.event(ctx, event, data, env);
     }
-}
\ No newline at end of file
+}
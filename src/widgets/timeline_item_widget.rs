@@ -1,25 +1,26 @@
-use druid::kurbo::{Circle, RoundedRect, BezPath};
+use druid::kurbo::{RoundedRect, BezPath};
 use druid::widget::prelude::*;
-use druid::{Widget, widget, WidgetExt};
-use druid::piet::{Color, kurbo};
+use druid::{Widget, widget, WidgetExt, im, lens};
+use druid::piet::kurbo;
 use druid::WidgetPod;
 use druid::Point;
 use druid;
-use crate::{MessageGroup, widgets::single_message_widget::SingleMessageWidget};
+use crate::{Message, MessageGroup, widgets::single_message_widget::SingleMessageWidget};
 use crate::LayoutSettings;
+use crate::helper::layout_settings::WithSettings;
 use crate::helper::helper_functions;
+use super::avatar_widget::AvatarPainter;
 use num_derive;
 
 extern crate chrono;
 
 pub struct TimelineItemWidget {
-    msg_content_labels: WidgetPod<MessageGroup, Box<dyn Widget<MessageGroup>>>,
-    sender_name_label: WidgetPod<MessageGroup, widget::Label<MessageGroup>>,
-    datetime_label: WidgetPod<MessageGroup, widget::Label<MessageGroup>>,
+    msg_content_labels: WidgetPod<WithSettings<MessageGroup>, Box<dyn Widget<WithSettings<MessageGroup>>>>,
+    sender_name_label: WidgetPod<WithSettings<MessageGroup>, widget::Label<WithSettings<MessageGroup>>>,
+    datetime_label: WidgetPod<WithSettings<MessageGroup>, widget::Label<WithSettings<MessageGroup>>>,
+    avatar_painter: AvatarPainter<WithSettings<MessageGroup>>,
 }
 
-const OTHER_MSG_COLOR: Color = Color::rgb8(74, 74, 76);
-const SELF_MSG_COLOR: Color = Color::rgb8(12, 131, 242);
 const DOT_SIZE: f64 = 1.5;
 const DOT_X_OFFSET: f64 = -0.9;
 const DOT_Y_OFFSET: f64 = 1.0;
@@ -31,6 +32,10 @@ pub enum PictureShape {
     Circle,
     Hexagon,
     Octagon,
+    Squircle,
+    RoundedHexagon,
+    /// Uses the outline loaded from an SVG file
+    CustomMask,
 }
 
 #[derive(Clone, Copy, PartialEq, Data, num_derive::FromPrimitive)]
@@ -59,6 +64,11 @@ pub enum MetadataLayout {
     LeftRightSpaced,
 }
 
+/// Groups with only emoji-only messages skip the bubble, the large emoji stand on their own
+fn shows_bubble(settings: &LayoutSettings, group: &MessageGroup) -> bool {
+    settings.is_bubble() && !group.is_emoji_only()
+}
+
 fn make_tail_path(center_x: f64, y_position: f64, shape: TailShape, flip_x: bool, flip_y: bool, tail_size: f64) -> kurbo::BezPath {
     let x_translation = if flip_x { -1.0 } else { 1.0 };
     let y_translation = if flip_y { -1.0 } else { 1.0 };
@@ -119,59 +129,20 @@ fn make_tail_path(center_x: f64, y_position: f64, shape: TailShape, flip_x: bool
     path
 }
 
-fn make_hexagon_path(start_x: f64, vertical_trim: f64, inset: f64, pic_width: f64) -> kurbo::BezPath {
-    let mut path = kurbo::BezPath::new();
-    let second_x = pic_width * inset;
-    let third_x = pic_width * (1.0 - inset);
-    let top_y = pic_width * vertical_trim;
-    let middle_y = pic_width / 2.0;
-    let bottom_y = pic_width * (1.0 - vertical_trim);
-    path.move_to(Point::new(0.0, middle_y)); // Start
-    path.line_to(Point::new( second_x, top_y));
-    path.line_to(Point::new( third_x, top_y));
-    path.line_to(Point::new( pic_width, middle_y));
-    path.line_to(Point::new( third_x, bottom_y));
-    path.line_to(Point::new( second_x, bottom_y));
-    path.line_to(Point::new(0.0, middle_y));
-    path.close_path();
-    path.apply_affine(druid::Affine::translate(druid::kurbo::Vec2::new(start_x, 0.0)));
-    path
-}
-
-fn make_octagon_path(start_x: f64, fraction_from_corner: f64, pic_width: f64) -> kurbo::BezPath {
-    let dist_from_corner = pic_width * fraction_from_corner;
-    let other_side_pos = pic_width - dist_from_corner;
-
-    let mut path = kurbo::BezPath::new();
-    path.move_to(Point::new(0.0, dist_from_corner)); // Start
-    path.line_to(Point::new( dist_from_corner, 0.0));
-    path.line_to(Point::new( other_side_pos, 0.0));
-    path.line_to(Point::new( pic_width, dist_from_corner));
-    path.line_to(Point::new( pic_width, other_side_pos));
-    path.line_to(Point::new( other_side_pos, pic_width));
-    path.line_to(Point::new( dist_from_corner, pic_width));
-    path.line_to(Point::new( 0.0, other_side_pos));
-    path.close_path();
-    path.apply_affine(druid::Affine::translate(druid::kurbo::Vec2::new(start_x, 0.0)));
-    path
-}
-
 impl TimelineItemWidget {
     pub fn new() -> Self {
         let sender_name_label = WidgetPod::new(
-            widget::Label::new(|item: &MessageGroup, _env: &_| {
-                let mut username = "User".to_string();
-                username.push_str(item.user_id.to_string().as_str());
-                username
+            widget::Label::new(|(_, item): &WithSettings<MessageGroup>, _env: &_| {
+                item.display_name.clone()
         })
             .with_line_break_mode(widget::LineBreaking::WordWrap)
         );
         let datetime_label = WidgetPod::new(
-            widget::Label::new(|item: &MessageGroup, env: &Env| {
+            widget::Label::new(|(settings, item): &WithSettings<MessageGroup>, _env: &_| {
                 if item.messages.len() > 0 {
                     helper_functions::timestamp_to_display_msg(
                         item.messages[0].timestamp_epoch_seconds,
-                        num_traits::FromPrimitive::from_u64(env.get(crate::DATETIME_FORMAT_KEY)).expect("Invalid datetime format index"),
+                        settings.datetime_format,
                     ).to_string()
                 } else {
                     "Invalid".to_string()
@@ -185,68 +156,77 @@ impl TimelineItemWidget {
         }).with_spacing(crate::SINGLE_MESSAGE_SPACING_KEY);
         let msg_content_labels = WidgetPod::new(
             // Boxed is needed to make it so you don't get buried in type annotations.
-            msg_content_labels_list.lens(MessageGroup::messages).boxed()
+            msg_content_labels_list.lens(lens::Map::new(
+                |(settings, group): &WithSettings<MessageGroup>| (settings.clone(), group.messages.clone()),
+                |(_, group): &mut WithSettings<MessageGroup>, (_, messages): WithSettings<im::Vector<Message>>| {
+                    group.messages = messages
+                },
+            )).boxed()
         );
         Self {
             msg_content_labels: msg_content_labels,
             sender_name_label: sender_name_label,
             datetime_label: datetime_label,
+            avatar_painter: AvatarPainter::new(),
         }
     }
 
 }
 
-impl Widget<MessageGroup> for TimelineItemWidget {
+impl Widget<WithSettings<MessageGroup>> for TimelineItemWidget {
 
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut MessageGroup, env: &Env) {
-        match event {
-            Event::Command(cmd) if cmd.is(crate::REFRESH_UI_SELECTOR) => {
-                self.msg_content_labels.event(ctx, event, data, env);
-                ctx.request_layout();
-                ctx.request_paint();
-            }
-            _ => {
-                self.msg_content_labels.event(ctx, event, data, env);
-                self.sender_name_label.event(ctx, event, data, env);
-                self.datetime_label.event(ctx, event, data, env);
-            }
-        }
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut WithSettings<MessageGroup>, env: &Env) {
+        self.msg_content_labels.event(ctx, event, data, env);
+        self.sender_name_label.event(ctx, event, data, env);
+        self.datetime_label.event(ctx, event, data, env);
+        self.avatar_painter.event(ctx, event, data, env);
     }
 
     fn lifecycle(
         &mut self,
         ctx: &mut LifeCycleCtx,
         event: &LifeCycle,
-        data: &MessageGroup,
+        data: &WithSettings<MessageGroup>,
         env: &Env,
     ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.apply_settings(data);
+        }
         self.msg_content_labels.lifecycle(ctx, event, data, env);
         self.sender_name_label.lifecycle(ctx, event, data, env);
         self.datetime_label.lifecycle(ctx, event, data, env);
+        self.avatar_painter.lifecycle(ctx, event, data, env);
     }
 
-    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &MessageGroup, data: &MessageGroup, env: &Env) {
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &WithSettings<MessageGroup>, data: &WithSettings<MessageGroup>, env: &Env) {
+        // A new snapshot means some setting changed, and any of them can affect the layout.
+        // The colors also depend on the sender, which changes when the list reuses this item for another group.
+        if !old_data.0.same(&data.0) || old_data.1.user_id != data.1.user_id {
+            self.apply_settings(data);
+            ctx.request_layout();
+        }
+        if !old_data.1.same(&data.1) && old_data.1.is_emoji_only() != data.1.is_emoji_only() {
+            ctx.request_paint();
+        }
+        // The profile picture is painted here rather than by a child, so nothing else repaints it
+        if !old_data.1.avatar.same(&data.1.avatar) || old_data.1.presence != data.1.presence {
+            ctx.request_paint();
+        }
         self.msg_content_labels.update(ctx, data, env);
         self.sender_name_label.update(ctx, data, env);
         self.datetime_label.update(ctx, data, env);
+        self.avatar_painter.update(ctx, data, env);
     }
 
     fn layout(
         &mut self,
         layout_ctx: &mut LayoutCtx,
         bc: &BoxConstraints,
-        data: &MessageGroup,
+        data: &WithSettings<MessageGroup>,
         env: &Env,
     ) -> Size {
-        let settings = LayoutSettings::from_env(env);
-        let is_self_user: bool = false;
-
-        self.sender_name_label.widget_mut().set_font(settings.get_metadata_font_descriptor());
-        self.datetime_label.widget_mut().set_font(settings.get_metadata_font_descriptor());
-        self.sender_name_label.widget_mut().set_text_size(crate::SENDER_FONT_SIZE_KEY);
-        self.datetime_label.widget_mut().set_text_size(crate::DATETIME_FONT_SIZE_KEY);
-        self.sender_name_label.widget_mut().set_text_color(settings.get_sender_color(is_self_user));
-        self.datetime_label.widget_mut().set_text_color(settings.get_datetime_color(is_self_user));
+        let settings = &data.0;
+        let is_self_user = data.1.is_self_user();
 
         let width_available = bc.max().width;
 
@@ -299,22 +279,25 @@ impl Widget<MessageGroup> for TimelineItemWidget {
         self.sender_name_label.set_origin(layout_ctx, sender_label_origin);
         self.datetime_label.set_origin(layout_ctx, datetime_label_origin);
 
+        self.avatar_painter.layout(layout_ctx, settings.picture_size, data, env);
+
         // The image is at the top left if other, or top right if self (if shown)
         // Potential future support for bottom images
         Size::new(bc.max().width, settings.get_total_height(width_available, &sender_label_size, &msg_label_list_size, y_top_offset))
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, data: &MessageGroup, env: &Env) {
-        let settings = LayoutSettings::from_env(env);
-        let is_self_user = false;
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &WithSettings<MessageGroup>, env: &Env) {
+        let (settings, group) = data;
+        let is_self_user = group.is_self_user();
+        let show_bubble = shows_bubble(settings, group);
     
         // First, do the calculations and variables
-        self.draw_bubble_background(ctx, &settings, is_self_user);
+        self.draw_bubble_background(ctx, settings, is_self_user, show_bubble);
 
         // Next, the profile pic
-        self.draw_profile_pic(ctx, data, &settings, is_self_user);
+        self.draw_profile_pic(ctx, data, env, is_self_user, show_bubble);
         // Now the little arrow/tail that goes from the image to the bubble
-        self.draw_bubble_tail(ctx, &settings, is_self_user);
+        self.draw_bubble_tail(ctx, settings, is_self_user, show_bubble);
 
         // Draw text
         self.msg_content_labels.paint(ctx, data, env);
@@ -332,9 +315,21 @@ impl Widget<MessageGroup> for TimelineItemWidget {
 
 impl TimelineItemWidget {
 
-    /// Gets the total space taken up by all labels in the bubble, minus the padding. 
+    /// Applies the label styling from the settings. Only needed when the settings or the sender change.
+    fn apply_settings(&mut self, data: &WithSettings<MessageGroup>) {
+        let (settings, group) = data;
+        let is_self_user = group.is_self_user();
+        self.sender_name_label.widget_mut().set_font(settings.get_metadata_font_descriptor());
+        self.datetime_label.widget_mut().set_font(settings.get_metadata_font_descriptor());
+        self.sender_name_label.widget_mut().set_text_size(settings.sender_font_size);
+        self.datetime_label.widget_mut().set_text_size(settings.datetime_font_size);
+        self.sender_name_label.widget_mut().set_text_color(settings.get_sender_color(is_self_user));
+        self.datetime_label.widget_mut().set_text_color(settings.get_datetime_color(is_self_user));
+    }
+
+    /// Gets the total space taken up by all labels in the bubble, plus the padding if the bubble is shown.
     /// Return order: x0, x1, y0, y1
-    fn get_bubble_dimensions(&self, settings: &LayoutSettings) -> (f64, f64, f64, f64) {
+    fn get_bubble_dimensions(&self, settings: &LayoutSettings, show_bubble: bool) -> (f64, f64, f64, f64) {
         let content_label_rect = self.msg_content_labels.layout_rect();
         let sender_label_rect = self.sender_name_label.layout_rect();
         let datetime_label_rect = self.datetime_label.layout_rect();
@@ -356,24 +351,16 @@ impl TimelineItemWidget {
 
         let bubble_y1 = bubble_y0 + unpadded_bubble_height;
 
-        (bubble_x0 - settings.bubble_padding, bubble_x1 + settings.bubble_padding,
-            bubble_y0 - settings.bubble_padding, bubble_y1 + settings.bubble_padding)
+        let padding = if show_bubble { settings.bubble_padding } else { 0.0 };
+        (bubble_x0 - padding, bubble_x1 + padding, bubble_y0 - padding, bubble_y1 + padding)
     }
 
-    fn get_bubble_color(&self, is_self_user: bool) -> druid::Color {
-        if is_self_user {
-            SELF_MSG_COLOR
-        } else {
-            OTHER_MSG_COLOR
-        }
-    }
-
-    fn draw_bubble_background(&self, ctx: &mut PaintCtx, settings: &LayoutSettings, is_self_user: bool) {
-        let (bubble_x0, bubble_x1, bubble_y0, bubble_y1) = self.get_bubble_dimensions(settings);
+    fn draw_bubble_background(&self, ctx: &mut PaintCtx, settings: &LayoutSettings, is_self_user: bool, show_bubble: bool) {
+        let (bubble_x0, bubble_x1, bubble_y0, bubble_y1) = self.get_bubble_dimensions(settings, show_bubble);
 
-        let bubble_color = self.get_bubble_color(is_self_user);
+        let bubble_color = settings.get_bubble_color(is_self_user);
         // Draw background
-        if settings.is_bubble() {
+        if show_bubble {
             let background_rect = RoundedRect::new(
                 bubble_x0, bubble_y0, bubble_x1, bubble_y1, settings.chat_bubble_radius
             );
@@ -381,14 +368,14 @@ impl TimelineItemWidget {
         }
     }
 
-    fn draw_bubble_tail(&self, ctx: &mut PaintCtx, settings: &LayoutSettings, is_self_user: bool) {
-        if settings.is_bubble() {
-            let (bubble_x0, bubble_x1, bubble_y0, bubble_y1) = self.get_bubble_dimensions(settings);
+    fn draw_bubble_tail(&self, ctx: &mut PaintCtx, settings: &LayoutSettings, is_self_user: bool, show_bubble: bool) {
+        if show_bubble {
+            let (bubble_x0, bubble_x1, bubble_y0, bubble_y1) = self.get_bubble_dimensions(settings, show_bubble);
 
             let is_flipped = settings.is_bubble_flipped(is_self_user);
             let tail_y_position = if is_flipped { bubble_y1 } else { bubble_y0 };
             let tail_x_position = if is_self_user { bubble_x1 } else { bubble_x0 };
-            let bubble_color = self.get_bubble_color(is_self_user);
+            let bubble_color = settings.get_bubble_color(is_self_user);
             
             if settings.chat_bubble_tail_shape != TailShape::Hidden {
                 ctx.fill(make_tail_path(
@@ -403,7 +390,8 @@ impl TimelineItemWidget {
         }
     }
 
-    fn draw_profile_pic(&self, ctx: &mut PaintCtx, data: &MessageGroup, settings: &LayoutSettings, is_self_user: bool) {
+    fn draw_profile_pic(&mut self, ctx: &mut PaintCtx, data: &WithSettings<MessageGroup>, env: &Env, is_self_user: bool, show_bubble: bool) {
+        let (settings, group) = data;
         if !settings.show_picture(is_self_user) {
             return;
         }
@@ -412,46 +400,15 @@ impl TimelineItemWidget {
             ctx.region().bounding_box().width(),
             self.sender_name_label.layout_rect().size()
         );
-        let piet_image = {
-            let image_data = data.profile_pic.clone();
-            image_data.to_image(ctx.render_ctx)
+        let pic_y_offset = if settings.is_bubble_flipped(is_self_user) && show_bubble {
+            let (_, _, _, bubble_y1) = self.get_bubble_dimensions(settings, show_bubble);
+
+            0.0f64.max(bubble_y1 - settings.picture_size) - 0.3
+        } else {
+            0.3 // For preventing some of the profile pic from showing over the tail
         };
-        ctx.with_save(|ctx| { // Makes it so the clip doesn't mess up the following draws
-            let pic_y_offset = if settings.is_bubble_flipped(is_self_user) && settings.is_bubble() {
-                let (_, _, _, bubble_y1) = self.get_bubble_dimensions(settings);
-
-                0.0f64.max(bubble_y1 - settings.picture_size) - 0.3
-            } else {
-                0.3 // For preventing some of the profile pic from showing over the tail
-            };
-            match settings.picture_shape {
-                PictureShape::Rectangle => {},
-                PictureShape::RoundedRectangle => {
-                    ctx.clip(
-                        RoundedRect::new(profile_pic_x_offset, 0.0, 
-                            profile_pic_x_offset + settings.picture_size, settings.picture_size, 4.0)
-                    )
-                },
-                PictureShape::Circle => {
-                    ctx.clip(Circle::new(
-                        Point::new(
-                            profile_pic_x_offset + settings.picture_size / 2.0,
-                            settings.picture_size / 2.0 + pic_y_offset), settings.picture_size / 2.0
-                        )
-                    )
-                },
-                PictureShape::Hexagon => {
-                    ctx.clip(make_hexagon_path(profile_pic_x_offset, 0.08, 0.25, settings.picture_size))
-                },
-                PictureShape::Octagon => {
-                    ctx.clip(make_octagon_path(profile_pic_x_offset, 0.25, settings.picture_size))
-                },
-            }
-            ctx.draw_image(&piet_image,
-                druid::Rect::new(profile_pic_x_offset, pic_y_offset,
-                    settings.picture_size + profile_pic_x_offset, settings.picture_size + pic_y_offset),
-                    druid::piet::InterpolationMode::Bilinear
-            );
-        });
+        let pic_rect = druid::Rect::new(profile_pic_x_offset, pic_y_offset,
+            settings.picture_size + profile_pic_x_offset, settings.picture_size + pic_y_offset);
+        self.avatar_painter.paint(ctx, data, env, settings, group.user_id, &group.avatar, group.presence, pic_rect);
     }
 }
\ No newline at end of file
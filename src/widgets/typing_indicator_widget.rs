@@ -0,0 +1,126 @@
+use std::f64::consts::TAU;
+
+use druid::kurbo::{Circle, Rect};
+use druid::text::TextLayout;
+use druid::widget::prelude::*;
+use druid::Point;
+use crate::AppState;
+use crate::typing_ui;
+use super::avatar_widget::AvatarPainter;
+
+/// Same as the timeline's padding, so the avatar lines up with the ones above it
+const ROW_PADDING: f64 = 5.0;
+const DOT_RADIUS: f64 = 2.0;
+const DOT_SPACING: f64 = 6.0;
+/// Space between the text and the first dot
+const DOTS_MARGIN: f64 = 4.0;
+/// How far up the dots bounce
+const DOT_BOUNCE_HEIGHT: f64 = 3.0;
+/// How long one wave through the dots takes
+const DOT_CYCLE_SECONDS: f64 = 1.2;
+/// How far behind the previous one each dot is, as a fraction of the cycle
+const DOT_DELAY: f64 = 0.15;
+
+/// "Alice is typing" with bouncing dots, under the newest message. Has the avatar of the first
+/// user typing where the timeline has its avatars. Takes no space when nobody is typing.
+pub struct TypingIndicatorWidget {
+    label: TextLayout<String>,
+    avatar_painter: AvatarPainter<AppState>,
+    /// Seconds into the current cycle of the dots
+    phase: f64,
+}
+
+impl TypingIndicatorWidget {
+    pub fn new() -> Self {
+        TypingIndicatorWidget { label: TextLayout::new(), avatar_painter: AvatarPainter::new(), phase: 0.0 }
+    }
+
+    /// Only needed when who is typing, or their names, change
+    fn rebuild_label(&mut self, data: &AppState) {
+        let names: Vec<String> = data.typing_users.iter().map(|user_id| data.get_display_name(*user_id)).collect();
+        self.label.set_text(typing_ui::typing_text(&names));
+    }
+}
+
+impl Widget<AppState> for TypingIndicatorWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        if let Event::AnimFrame(interval) = event {
+            if !data.typing_users.is_empty() {
+                self.phase = (self.phase + *interval as f64 / 1e9) % DOT_CYCLE_SECONDS;
+                ctx.request_paint();
+                ctx.request_anim_frame();
+            }
+        }
+        self.avatar_painter.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild_label(data);
+            if !data.typing_users.is_empty() {
+                ctx.request_anim_frame();
+            }
+        }
+        self.avatar_painter.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        if !old_data.typing_users.same(&data.typing_users) || !old_data.display_names.same(&data.display_names) {
+            self.rebuild_label(data);
+            if old_data.typing_users.is_empty() && !data.typing_users.is_empty() {
+                self.phase = 0.0;
+                ctx.request_anim_frame();
+            }
+            ctx.request_layout();
+        } else if !old_data.layout_settings.same(&data.layout_settings) {
+            ctx.request_layout();
+        } else if !old_data.avatars.same(&data.avatars) || !old_data.presences.same(&data.presences) {
+            ctx.request_paint();
+        }
+        self.avatar_painter.update(ctx, data, env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &AppState, env: &Env) -> Size {
+        if data.typing_users.is_empty() {
+            return bc.constrain(Size::new(bc.max().width, 0.0));
+        }
+        let settings = &data.layout_settings;
+        self.label.set_text_size(settings.datetime_font_size);
+        self.label.set_text_color(settings.datetime_color.to_druid_color());
+        self.label.rebuild_if_needed(ctx.text(), env);
+        self.avatar_painter.layout(ctx, settings.picture_size, data, env);
+        let height = settings.picture_size.max(self.label.size().height) + ROW_PADDING * 2.0;
+        bc.constrain(Size::new(bc.max().width, height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let first_user_id = match data.typing_users.front() {
+            Some(user_id) => *user_id,
+            None => return,
+        };
+        let settings = &data.layout_settings;
+        let width_available = ctx.size().width - ROW_PADDING * 2.0;
+        // Others' avatars are on the left in every layout
+        let pic_x = ROW_PADDING + settings.profile_pic_x_origin(false, width_available, Size::ZERO);
+        let pic_rect = Rect::from_origin_size(Point::new(pic_x, ROW_PADDING), Size::new(settings.picture_size, settings.picture_size));
+        self.avatar_painter.paint(ctx, data, env, settings, first_user_id,
+            &data.get_avatar(first_user_id), data.get_presence(first_user_id), pic_rect);
+
+        let label_size = self.label.size();
+        let text_x = ROW_PADDING + settings.left_spacing
+            + settings.get_unpadded_content_x_left_position(false, width_available, label_size.width, 0.0);
+        let center_y = pic_rect.center().y;
+        self.label.draw(ctx, Point::new(text_x, center_y - label_size.height / 2.0));
+
+        let dot_color = settings.datetime_color.to_druid_color();
+        let first_dot_x = text_x + label_size.width + DOTS_MARGIN + DOT_RADIUS;
+        let dot_y = center_y + label_size.height / 4.0;
+        for index in 0..3 {
+            // Each dot bounces once per cycle, a little after the one before it
+            let progress = (self.phase / DOT_CYCLE_SECONDS - index as f64 * DOT_DELAY).rem_euclid(1.0);
+            let bounce = (progress * TAU).sin().max(0.0) * DOT_BOUNCE_HEIGHT;
+            let center = Point::new(first_dot_x + index as f64 * DOT_SPACING, dot_y - bounce);
+            ctx.fill(Circle::new(center, DOT_RADIUS), &dot_color);
+        }
+    }
+}